@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use crate::font::{Font, FontChar};
+
+/// Glyph being read between STARTCHAR and ENDCHAR.
+#[derive(Default)]
+struct Glyph {
+    encoding: Option<u32>,
+    /// (width, height, x offset, y offset) with the y offset up from the
+    /// baseline.
+    bbx: Option<(usize, usize, isize, isize)>,
+    advance: Option<usize>,
+    rows: Vec<Vec<u8>>,
+    in_bitmap: bool,
+}
+
+fn numbers<T: std::str::FromStr>(args: &[&str], count: usize, line_no: usize) -> Result<Vec<T>, String> {
+    let values: Vec<T> = args.iter()
+        .take(count)
+        .map(|a| a.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Line {}: expected {} numbers", line_no, count))?;
+
+    if values.len() < count {
+        return Err(format!("Line {}: expected {} numbers", line_no, count));
+    }
+
+    Ok(values)
+}
+
+/// A property value with any quotes taken off.
+fn property(args: &[&str]) -> String {
+    args.join(" ").trim_matches('"').to_string()
+}
+
+/// Whether ENCODING values in a font with this CHARSET_REGISTRY and
+/// CHARSET_ENCODING are Unicode code points. ISO 8859-1 is the first 256 of
+/// them. Fonts that don't say are taken to be Unicode.
+fn is_unicode(registry: Option<&str>, encoding: Option<&str>) -> bool {
+    match (registry, encoding) {
+        (None, _) => true,
+        (Some(registry), _) if registry.eq_ignore_ascii_case("ISO10646") => true,
+        (Some(registry), Some("1")) => registry.eq_ignore_ascii_case("ISO8859"),
+        _ => false,
+    }
+}
+
+/// One bitmap row of hex digits as one byte per pixel, most significant bit
+/// first.
+fn bitmap_row(hex: &str, width: usize, line_no: usize) -> Result<Vec<u8>, String> {
+    let bits: Vec<u8> = hex.chars()
+        .map(|c| c.to_digit(16).ok_or_else(|| format!("Line {}: bad bitmap row \"{}\"", line_no, hex)))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flat_map(|d| (0..4).rev().map(move |b| ((d >> b) & 1) as u8))
+        .collect();
+
+    if bits.len() < width {
+        return Err(format!("Line {}: bitmap row \"{}\" is narrower than the glyph", line_no, hex));
+    }
+
+    Ok(bits[..width].to_vec())
+}
+
+/// Parse a font in the Glyph Bitmap Distribution Format, as used by the X11
+/// misc fonts and Spleen. Glyphs are placed by their BBX bounding boxes and
+/// advance by DWIDTH, falling back to the font wide FONTBOUNDINGBOX and
+/// DWIDTH. Lines are FONT_ASCENT + FONT_DESCENT tall, or as tall as the
+/// font bounding box without them. Only Unicode (ISO10646) and Latin-1
+/// (ISO8859-1) fonts are read, as other charsets would need their
+/// encodings mapped to Unicode first. Glyphs with an ENCODING of -1 are
+/// skipped.
+pub fn parse(text: &str) -> Result<Font, String> {
+    let mut started = false;
+    let mut font_bbx = None;
+    let mut font_advance = None;
+    let mut ascent = None;
+    let mut descent = None;
+    let mut registry = None;
+    let mut charset_encoding = None;
+    let mut glyph: Option<Glyph> = None;
+    let mut glyphs = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+
+        if !started {
+            if keyword != "STARTFONT" {
+                return Err("Not a BDF font, no STARTFONT".to_string());
+            }
+            started = true;
+            continue;
+        }
+
+        if let Some(g) = glyph.as_mut() {
+            match keyword {
+                "ENDCHAR" => glyphs.push(glyph.take().unwrap()),
+                _ if g.in_bitmap => {
+                    let (width, ..) = g.bbx.or(font_bbx)
+                        .ok_or_else(|| format!("Line {}: glyph has no BBX", line_no))?;
+                    g.rows.push(bitmap_row(keyword, width, line_no)?);
+                },
+                "ENCODING" => {
+                    // -1 marks a glyph outside the standard encoding
+                    let code: i64 = numbers(&args, 1, line_no)?[0];
+                    g.encoding = u32::try_from(code).ok();
+                },
+                "DWIDTH" => g.advance = Some(numbers(&args, 1, line_no)?[0]),
+                "BBX" => {
+                    let n: Vec<isize> = numbers(&args, 4, line_no)?;
+                    if n[0] < 0 || n[1] < 0 {
+                        return Err(format!("Line {}: negative glyph size", line_no));
+                    }
+                    g.bbx = Some((n[0] as usize, n[1] as usize, n[2], n[3]));
+                },
+                "BITMAP" => g.in_bitmap = true,
+                _ => {},
+            }
+            continue;
+        }
+
+        match keyword {
+            "FONTBOUNDINGBOX" => {
+                let n: Vec<isize> = numbers(&args, 4, line_no)?;
+                font_bbx = Some((n[0].max(0) as usize, n[1].max(0) as usize, n[2], n[3]));
+            },
+            "DWIDTH" => font_advance = Some(numbers(&args, 1, line_no)?[0]),
+            "FONT_ASCENT" => ascent = Some(numbers::<isize>(&args, 1, line_no)?[0]),
+            "FONT_DESCENT" => descent = Some(numbers::<isize>(&args, 1, line_no)?[0]),
+            "CHARSET_REGISTRY" => registry = Some(property(&args)),
+            "CHARSET_ENCODING" => charset_encoding = Some(property(&args)),
+            "STARTCHAR" => glyph = Some(Glyph::default()),
+            "ENDFONT" => break,
+            _ => {},
+        }
+    }
+
+    if glyph.is_some() {
+        return Err("Glyph without ENDCHAR".to_string());
+    }
+
+    if !is_unicode(registry.as_deref(), charset_encoding.as_deref()) {
+        return Err(format!("Charset {}-{} isn't Unicode or Latin-1",
+                           registry.unwrap_or_default(), charset_encoding.unwrap_or_default()));
+    }
+
+    let (box_width, box_height, _, box_y) = font_bbx.unwrap_or_default();
+    let descent = descent.unwrap_or(-box_y);
+    let ascent = ascent.unwrap_or(box_height as isize - descent);
+
+    let mut char_map = HashMap::new();
+    for g in glyphs {
+        let Some(c) = g.encoding.and_then(char::from_u32) else {
+            continue;
+        };
+        let (width, height, x_offset, y_offset) = g.bbx.or(font_bbx)
+            .ok_or_else(|| format!("Glyph {:?} has no BBX", c))?;
+
+        if g.rows.len() != height {
+            return Err(format!("Glyph {:?} has {} bitmap rows, expected {}", c, g.rows.len(), height));
+        }
+
+        let advance = g.advance.or(font_advance).unwrap_or(box_width);
+        let data = g.rows.concat();
+        let top = ascent - (y_offset + height as isize);
+        char_map.insert(c, FontChar::with_metrics(width, height, data, x_offset, top, advance));
+    }
+
+    Ok(Font::from_chars(char_map, 0, Some((ascent + descent).max(0) as usize)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::{ImgBuffer, Rgb};
+
+    /// A 4x6 cell font with a baseline one row up from the bottom.
+    const FONT: &str = "\
+STARTFONT 2.1
+FONT -test-tiny
+SIZE 6 75 75
+FONTBOUNDINGBOX 4 6 0 -1
+STARTPROPERTIES 4
+FONT_ASCENT 5
+FONT_DESCENT 1
+CHARSET_REGISTRY \"ISO10646\"
+CHARSET_ENCODING \"1\"
+ENDPROPERTIES
+CHARS 3
+STARTCHAR period
+ENCODING 46
+DWIDTH 2 0
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+STARTCHAR j
+ENCODING 106
+DWIDTH 4 0
+BBX 3 5 0 -1
+BITMAP
+20
+00
+20
+20
+C0
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+DWIDTH 4 0
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn glyphs_keep_their_bounds() {
+        let font = parse(FONT).unwrap();
+        assert_eq!(font.line_height(), 6);
+        assert_eq!(font.char_spacing(), 0);
+
+        let period = font.char(&'.').unwrap();
+        assert_eq!((period.width(), period.height()), (1, 1));
+        assert_eq!((period.x_offset(), period.y_offset(), period.advance()), (0, 4, 2));
+
+        let j = font.char(&'j').unwrap();
+        assert_eq!((j.x_offset(), j.y_offset(), j.advance()), (0, 1, 4));
+        assert_eq!(j.data(), &[0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn text_advances_by_dwidth() {
+        let font = parse(FONT).unwrap();
+        assert_eq!(font.text_width(".j."), 8);
+        assert_eq!(font.len(), 2);
+    }
+
+    #[test]
+    fn glyphs_are_drawn_at_their_offsets() {
+        let font = parse(FONT).unwrap();
+        let mut image = ImgBuffer::new(6, 6);
+        image.draw_str(".j", &font, 0, 0, Rgb::new(255, 255, 255));
+
+        let lit = |x, y| image.pixel(x, y) != Rgb::default();
+        assert!(lit(0, 4));
+        assert!(lit(4, 1) && !lit(4, 2) && lit(4, 3));
+        assert!(lit(2, 5) && lit(3, 5));
+        assert!(!lit(0, 0));
+    }
+
+    #[test]
+    fn only_unicode_and_latin1_charsets_load() {
+        let latin1 = FONT.replace("\"ISO10646\"", "\"ISO8859\"");
+        assert!(parse(&latin1).is_ok());
+
+        let latin2 = latin1.replace("CHARSET_ENCODING \"1\"", "CHARSET_ENCODING \"2\"");
+        assert!(parse(&latin2).is_err());
+
+        let koi8 = FONT.replace("\"ISO10646\"", "\"KOI8\"").replace("\"1\"", "\"R\"");
+        assert!(parse(&koi8).is_err());
+    }
+
+    #[test]
+    fn bad_fonts_are_errors() {
+        assert!(parse("FONT nope").is_err());
+        assert!(parse(&FONT.replace("C0\n", "")).is_err());
+        assert!(parse(&FONT.replace("20\n00", "2x\n00")).is_err());
+    }
+}
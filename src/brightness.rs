@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use serde::Deserialize;
+
+/// Shared panel brightness in percent. Clones refer to the same setting, so
+/// one thread can dim the panel while another is strobing it.
+#[derive(Clone, Debug)]
+pub struct Brightness(Arc<AtomicU8>);
+
+impl Brightness {
+    pub fn new(percent: u8) -> Self {
+        Self(Arc::new(AtomicU8::new(percent.min(100))))
+    }
+
+    pub fn get(&self) -> u8 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, percent: u8) {
+        self.0.store(percent.min(100), Ordering::Relaxed);
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SchedulePoint {
+    /// Time of day as "HH:MM".
+    pub time: String,
+    /// Brightness in percent.
+    pub level: u8,
+}
+
+/// Brightness over the day, faded linearly between the points of a table.
+/// The fade from the last point of the day wraps around to the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrightnessSchedule {
+    /// Minutes after midnight and brightness, sorted by time.
+    points: Vec<(u32, u8)>,
+}
+
+fn parse_time(time: &str) -> Result<u32, String> {
+    let parsed = time.split_once(':').and_then(|(h, m)| {
+        let h: u32 = h.trim().parse().ok()?;
+        let m: u32 = m.trim().parse().ok()?;
+        (h < 24 && m < 60).then_some(h * 60 + m)
+    });
+
+    parsed.ok_or_else(|| format!("Invalid time of day \"{}\", expected HH:MM", time))
+}
+
+impl BrightnessSchedule {
+    pub fn new(points: &[SchedulePoint]) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("Brightness schedule needs at least one point".to_string());
+        }
+
+        let mut parsed = points.iter()
+            .map(|p| Ok((parse_time(&p.time)?, p.level.min(100))))
+            .collect::<Result<Vec<_>, String>>()?;
+        parsed.sort_by_key(|p| p.0);
+
+        Ok(Self { points: parsed })
+    }
+
+    /// Brightness at `minute` minutes after midnight.
+    pub fn level_at(&self, minute: u32) -> u8 {
+        let day = 24 * 60;
+        let minute = minute % day;
+
+        // The point at or before `minute`, wrapping back to the last point
+        // of the previous day
+        let next = self.points.iter().position(|p| p.0 > minute).unwrap_or(self.points.len());
+        let (t0, l0) = self.points[(next + self.points.len() - 1) % self.points.len()];
+        let (t1, l1) = self.points[next % self.points.len()];
+
+        let span = (t1 + day - t0) % day;
+        if span == 0 {
+            return l0;
+        }
+
+        let elapsed = (minute + day - t0) % day;
+        let level = l0 as i32 + (l1 as i32 - l0 as i32) * elapsed as i32 / span as i32;
+        level as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(time: &str, level: u8) -> SchedulePoint {
+        SchedulePoint { time: time.to_string(), level }
+    }
+
+    #[test]
+    fn brightness_is_clamped() {
+        let brightness = Brightness::new(150);
+        assert_eq!(brightness.get(), 100);
+
+        let shared = brightness.clone();
+        shared.set(30);
+        assert_eq!(brightness.get(), 30);
+    }
+
+    #[test]
+    fn schedule_fades_between_points() {
+        let schedule = BrightnessSchedule::new(&[
+            point("20:00", 10),
+            point("07:00", 100),
+            point("06:00", 10),
+        ]).unwrap();
+
+        assert_eq!(schedule.level_at(6 * 60), 10);
+        assert_eq!(schedule.level_at(6 * 60 + 30), 55);
+        assert_eq!(schedule.level_at(12 * 60), 66);
+        assert_eq!(schedule.level_at(20 * 60), 10);
+        assert_eq!(schedule.level_at(2 * 60), 10);
+    }
+
+    #[test]
+    fn schedule_wraps_past_midnight() {
+        let schedule = BrightnessSchedule::new(&[
+            point("23:00", 0),
+            point("01:00", 100),
+        ]).unwrap();
+
+        assert_eq!(schedule.level_at(0), 50);
+        assert_eq!(schedule.level_at(12 * 60), 50);
+    }
+
+    #[test]
+    fn single_point_is_constant() {
+        let schedule = BrightnessSchedule::new(&[point("12:00", 40)]).unwrap();
+        assert_eq!(schedule.level_at(0), 40);
+        assert_eq!(schedule.level_at(13 * 60), 40);
+    }
+
+    #[test]
+    fn invalid_schedules() {
+        assert!(BrightnessSchedule::new(&[]).is_err());
+        assert!(BrightnessSchedule::new(&[point("25:00", 10)]).is_err());
+        assert!(BrightnessSchedule::new(&[point("noon", 10)]).is_err());
+    }
+}
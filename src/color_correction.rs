@@ -0,0 +1,118 @@
+use serde::Deserialize;
+use crate::img_buffer::Rgb;
+
+/// Gamma curve and per-channel white balance applied when an image is
+/// converted for the panel.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ColorCorrection {
+    pub gamma: f32,
+    /// Scale for the red, green and blue LEDs, from 0.0 to 1.0.
+    pub white_balance: [f32; 3],
+}
+
+impl ColorCorrection {
+    /// Pass values through unchanged.
+    pub const LINEAR: ColorCorrection = ColorCorrection {
+        gamma: 1.0,
+        white_balance: [1.0, 1.0, 1.0],
+    };
+}
+
+impl Default for ColorCorrection {
+    fn default() -> Self {
+        Self {
+            gamma: 2.2,
+            ..Self::LINEAR
+        }
+    }
+}
+
+/// Lookup tables from 8 bit channel values to the levels a panel with a
+/// given color depth displays.
+pub struct ColorTables {
+    channels: [[u8; 256]; 3],
+}
+
+impl ColorTables {
+    pub fn new(correction: &ColorCorrection, depth: usize) -> Self {
+        let levels = (1u32 << depth) as f32;
+        let mut channels = [[0; 256]; 3];
+
+        for (table, scale) in channels.iter_mut().zip(correction.white_balance) {
+            for (value, level) in table.iter_mut().enumerate() {
+                let linear = (value as f32 / 255.0).powf(correction.gamma) * scale.clamp(0.0, 1.0);
+                *level = (linear * levels).min(levels - 1.0) as u8;
+            }
+        }
+
+        Self { channels }
+    }
+
+    pub fn apply(&self, color: Rgb) -> Rgb {
+        Rgb::new(
+            self.channels[0][color.r as usize],
+            self.channels[1][color.g as usize],
+            self.channels[2][color.b as usize],
+        )
+    }
+
+    /// The darkest color the panel shows at the same levels as `color`. The
+    /// panel lights a level in proportion to it, so this is also roughly how
+    /// `color` looks on the panel.
+    pub fn quantize(&self, color: Rgb) -> Rgb {
+        // The tables only ever go up, so the first value at a level is the
+        // darkest
+        let snap = |table: &[u8; 256], v: u8| {
+            table.iter().position(|&level| level == table[v as usize]).unwrap() as u8
+        };
+
+        Rgb::new(
+            snap(&self.channels[0], color.r),
+            snap(&self.channels[1], color.g),
+            snap(&self.channels[2], color.b),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_keeps_the_top_bits() {
+        let tables = ColorTables::new(&ColorCorrection::LINEAR, 4);
+
+        for value in 0..=255u8 {
+            let level = tables.apply(Rgb::new(value, 0, 0)).r;
+            assert_eq!(level, value >> 4);
+        }
+    }
+
+    #[test]
+    fn gamma_darkens_mid_tones() {
+        let tables = ColorTables::new(&ColorCorrection::default(), 8);
+        let mid = tables.apply(Rgb::new(128, 128, 128));
+
+        assert_eq!(mid, Rgb::new(56, 56, 56));
+        assert_eq!(tables.apply(Rgb::new(255, 0, 0)), Rgb::new(255, 0, 0));
+    }
+
+    #[test]
+    fn white_balance_scales_channels() {
+        let correction = ColorCorrection {
+            white_balance: [1.0, 0.5, 0.25],
+            ..ColorCorrection::LINEAR
+        };
+        let tables = ColorTables::new(&correction, 8);
+
+        assert_eq!(tables.apply(Rgb::new(255, 255, 255)), Rgb::new(255, 128, 64));
+    }
+
+    #[test]
+    fn it_can_be_configured() {
+        let correction: ColorCorrection = toml::from_str("gamma = 1.8").unwrap();
+        assert_eq!(correction.gamma, 1.8);
+        assert_eq!(correction.white_balance, [1.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::read_to_string;
+use serde::Deserialize;
+use crate::brightness::{BrightnessSchedule, SchedulePoint};
+use crate::color_correction::ColorCorrection;
+use crate::driver_chip::DriverChip;
+use crate::hub75::{DeadTime, ScanRate};
+use crate::layout::{PanelLayout, Rotation};
+use crate::mapping::Mapping;
+use crate::realtime::RealtimeConfig;
+use crate::transform::DisplayTransform;
+
+/// Settings read from the sign's TOML config file.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub brightness: BrightnessConfig,
+    #[serde(default)]
+    pub color: ColorCorrection,
+    #[serde(default)]
+    pub panel: PanelConfig,
+    #[serde(default)]
+    pub strobe: RealtimeConfig,
+    #[serde(default)]
+    pub display: DisplayTransform,
+}
+
+/// How the panel pins are driven.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PinBackend {
+    /// Whole GPIO register writes through /dev/gpiomem.
+    #[default]
+    GpioMem,
+    /// One pin at a time through rppal, slower but handy for debugging.
+    Rppal,
+}
+
+/// Where the board is shown.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// The HUB75 panels.
+    #[default]
+    Panel,
+    /// A simulation in the terminal, for running without a Pi.
+    Terminal,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PanelConfig {
+    #[serde(default)]
+    pub output: Output,
+    #[serde(default)]
+    pub backend: PinBackend,
+    /// Times each clock edge is written with the gpiomem backend. Pi 4
+    /// boards usually need 2 or more for the panel to keep up.
+    #[serde(default = "default_slowdown")]
+    pub slowdown: usize,
+    #[serde(default)]
+    pub layout: LayoutConfig,
+    /// Name of the entry in `models` describing the panels in use.
+    pub model: Option<String>,
+    /// Timing for each panel model.
+    #[serde(default)]
+    pub models: HashMap<String, DeadTime>,
+}
+
+/// The modules, how they are wired and how they are chained together. The
+/// defaults are a single 128x32 module at 1/16 scan.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Size of one module in pixels.
+    pub cols: usize,
+    pub rows: usize,
+    /// Number of modules behind the connector.
+    pub chain: usize,
+    pub tile_rows: usize,
+    pub serpentine: bool,
+    /// Rotation of each module in chain order, in degrees.
+    pub rotations: Vec<Rotation>,
+    pub scan: ScanRate,
+    pub mapping: Mapping,
+    pub driver: DriverChip,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            cols: 128,
+            rows: 32,
+            chain: 1,
+            tile_rows: 1,
+            serpentine: false,
+            rotations: vec![],
+            scan: ScanRate::default(),
+            mapping: Mapping::default(),
+            driver: DriverChip::default(),
+        }
+    }
+}
+
+impl LayoutConfig {
+    pub fn layout(&self) -> PanelLayout {
+        PanelLayout {
+            tile_rows: self.tile_rows,
+            rotations: self.rotations.clone(),
+            serpentine: self.serpentine,
+            mapping: self.mapping.build(),
+            ..PanelLayout::chain(self.cols, self.rows, self.chain)
+        }
+    }
+
+    /// Check the layout is possible and the module height fits the scan
+    /// rate, as `Hub75Panel::new` would otherwise panic.
+    pub fn validate(&self) -> Result<(), String> {
+        let layout = self.layout();
+        layout.validate()?;
+
+        let rows = 2 * self.scan.row_count() * layout.mapping.factor();
+        if layout.panel_rows != rows {
+            return Err(format!("1/{} scan with this mapping needs modules {} rows tall, not {}",
+                               self.scan.row_count(), rows, layout.panel_rows));
+        }
+
+        Ok(())
+    }
+}
+
+fn default_slowdown() -> usize {
+    1
+}
+
+impl Default for PanelConfig {
+    fn default() -> Self {
+        Self {
+            output: Output::default(),
+            backend: PinBackend::default(),
+            slowdown: default_slowdown(),
+            layout: LayoutConfig::default(),
+            model: None,
+            models: HashMap::new(),
+        }
+    }
+}
+
+impl PanelConfig {
+    pub fn dead_time(&self) -> Result<DeadTime, String> {
+        match &self.model {
+            Some(model) => self.models.get(model).copied()
+                .ok_or_else(|| format!("No timing for panel model \"{}\"", model)),
+            None => Ok(DeadTime::default()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BrightnessConfig {
+    /// Brightness in percent, used when there is no schedule.
+    #[serde(default = "default_level")]
+    pub level: u8,
+    #[serde(default)]
+    pub schedule: Vec<SchedulePoint>,
+}
+
+fn default_level() -> u8 {
+    100
+}
+
+impl Default for BrightnessConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            schedule: vec![],
+        }
+    }
+}
+
+impl BrightnessConfig {
+    pub fn schedule(&self) -> Result<BrightnessSchedule, String> {
+        if self.schedule.is_empty() {
+            let constant = SchedulePoint { time: "00:00".to_string(), level: self.level };
+            BrightnessSchedule::new(&[constant])
+        } else {
+            BrightnessSchedule::new(&self.schedule)
+        }
+    }
+}
+
+impl Config {
+    pub fn load(filename: &str) -> Result<Self, Box<dyn Error>> {
+        let config_text = read_to_string(filename)?;
+        let config: Config = toml::from_str(&config_text)?;
+        config.brightness.schedule()?;
+        config.panel.dead_time()?;
+        config.panel.layout.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_sections_use_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.brightness.level, 100);
+        assert_eq!(config.brightness.schedule().unwrap().level_at(0), 100);
+        assert_eq!(config.color, ColorCorrection::default());
+        assert_eq!(config.panel.output, Output::Panel);
+        assert_eq!(config.panel.backend, PinBackend::GpioMem);
+        assert_eq!(config.panel.slowdown, 1);
+        assert_eq!(config.panel.layout, LayoutConfig::default());
+        assert_eq!(config.strobe, RealtimeConfig::default());
+        assert_eq!(config.display, DisplayTransform::default());
+    }
+
+    #[test]
+    fn it_can_load_sign_config() {
+        let config = Config::load("sign.toml").unwrap();
+        assert!(!config.brightness.schedule.is_empty());
+    }
+
+    #[test]
+    fn pin_backend_can_be_chosen() {
+        let config: Config = toml::from_str("[panel]\nbackend = \"rppal\"").unwrap();
+        assert_eq!(config.panel.backend, PinBackend::Rppal);
+        assert_eq!(config.panel.slowdown, 1);
+
+        let config: Config = toml::from_str("[panel]\nslowdown = 3").unwrap();
+        assert_eq!(config.panel.slowdown, 3);
+    }
+
+    #[test]
+    fn terminal_output_can_be_chosen() {
+        let config: Config = toml::from_str("[panel]\noutput = \"terminal\"").unwrap();
+        assert_eq!(config.panel.output, Output::Terminal);
+    }
+
+    #[test]
+    fn dead_time_comes_from_panel_model() {
+        let config: Config = toml::from_str(r#"
+            [panel]
+            model = "outdoor"
+
+            [panel.models.outdoor]
+            blank_ns = 200
+            settle_ns = 500
+
+            [panel.models.indoor]
+            precharge_ns = 100
+        "#).unwrap();
+
+        assert_eq!(config.panel.dead_time().unwrap(), DeadTime {
+            blank_ns: 200,
+            settle_ns: 500,
+            precharge_ns: 0,
+        });
+    }
+
+    #[test]
+    fn layout_can_be_configured() {
+        let config: Config = toml::from_str(r#"
+            [panel.layout]
+            cols = 32
+            rows = 16
+            chain = 4
+            tile_rows = 2
+            serpentine = true
+            scan = 4
+            mapping = { zstripe = { block = 8, upper_first = true } }
+            driver = "fm6126a"
+        "#).unwrap();
+
+        let layout = &config.panel.layout;
+        layout.validate().unwrap();
+        assert_eq!(layout.scan, ScanRate::Quarter);
+        assert_eq!(layout.mapping, Mapping::ZStripe { block: 8, upper_first: true });
+        assert_eq!(layout.driver, DriverChip::Fm6126a);
+        assert_eq!((layout.layout().width(), layout.layout().height()), (64, 32));
+    }
+
+    #[test]
+    fn layout_must_fit_scan_rate() {
+        let config: Config = toml::from_str("[panel.layout]\nrows = 32\nscan = 8").unwrap();
+        assert!(config.panel.layout.validate().is_err());
+        assert!(toml::from_str::<Config>("[panel.layout]\nscan = 12").is_err());
+    }
+
+    #[test]
+    fn unknown_panel_model() {
+        let config: Config = toml::from_str("[panel]\nmodel = \"missing\"").unwrap();
+        assert!(config.panel.dead_time().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::img_buffer::{ImgBuffer, Rect, Rgb};
+
+/// Points (x, y) of one octant of a circle of radius `r` around the origin,
+/// from (r, 0) up to the diagonal, by the midpoint algorithm.
+fn octant(r: isize) -> Vec<(isize, isize)> {
+    let mut points = vec![];
+    let (mut x, mut y) = (r, 0);
+    let mut err = 1 - r;
+
+    while x >= y {
+        points.push((x, y));
+        y += 1;
+
+        if err < 0 {
+            err += 2 * y + 1;
+        } else {
+            x -= 1;
+            err += 2 * (y - x) + 1;
+        }
+    }
+
+    points
+}
+
+/// Largest corner radius that fits in `rect`.
+fn fit_radius(rect: &Rect, radius: usize) -> isize {
+    let limit = (rect.width.min(rect.height).saturating_sub(1) / 2) as isize;
+    (radius as isize).min(limit)
+}
+
+/// Shapes drawn with the pixel colors as given. Everything is clipped like
+/// the other drawing calls.
+impl ImgBuffer {
+    fn hline(&mut self, x0: isize, x1: isize, y: isize, color: Rgb) {
+        for x in x0..=x1 {
+            self.plot(x, y, color);
+        }
+    }
+
+    fn vline(&mut self, x: isize, y0: isize, y1: isize, color: Rgb) {
+        for y in y0..=y1 {
+            self.plot(x, y, color);
+        }
+    }
+
+    /// A line from (x0, y0) to (x1, y1), both ends included.
+    pub fn draw_line<C: Into<Rgb>>(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: C) {
+        let color = color.into();
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = (x1 - x0).signum();
+        let sy = (y1 - y0).signum();
+
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// A one pixel outline just inside `rect`.
+    pub fn draw_rect<C: Into<Rgb>>(&mut self, rect: Rect, color: C) {
+        self.draw_rounded_rect(rect, 0, color);
+    }
+
+    pub fn fill_rect<C: Into<Rgb>>(&mut self, rect: Rect, color: C) {
+        let color = color.into();
+        let area = rect.intersect(&self.clip());
+
+        for y in area.y..area.bottom() {
+            self.hline(area.x, area.right() - 1, y, color);
+        }
+    }
+
+    /// A one pixel outline just inside `rect` with corners of `radius`. The
+    /// radius is reduced if the rectangle is too small for it.
+    pub fn draw_rounded_rect<C: Into<Rgb>>(&mut self, rect: Rect, radius: usize, color: C) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+
+        let color = color.into();
+        let r = fit_radius(&rect, radius);
+        let (left, right) = (rect.x + r, rect.right() - 1 - r);
+        let (top, bottom) = (rect.y + r, rect.bottom() - 1 - r);
+
+        self.hline(left, right, rect.y, color);
+        self.hline(left, right, rect.bottom() - 1, color);
+        self.vline(rect.x, top, bottom, color);
+        self.vline(rect.right() - 1, top, bottom, color);
+
+        for (x, y) in octant(r) {
+            for (dx, dy) in [(x, y), (y, x)] {
+                self.plot(left - dx, top - dy, color);
+                self.plot(right + dx, top - dy, color);
+                self.plot(left - dx, bottom + dy, color);
+                self.plot(right + dx, bottom + dy, color);
+            }
+        }
+    }
+
+    pub fn fill_rounded_rect<C: Into<Rgb>>(&mut self, rect: Rect, radius: usize, color: C) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+
+        let color = color.into();
+        let r = fit_radius(&rect, radius);
+        let (left, right) = (rect.x + r, rect.right() - 1 - r);
+        let (top, bottom) = (rect.y + r, rect.bottom() - 1 - r);
+
+        self.fill_rect(Rect::new(rect.x, top, rect.width, (bottom - top + 1) as usize), color);
+
+        for (x, y) in octant(r) {
+            for (dx, dy) in [(x, y), (y, x)] {
+                self.hline(left - dx, right + dx, top - dy, color);
+                self.hline(left - dx, right + dx, bottom + dy, color);
+            }
+        }
+    }
+
+    pub fn draw_circle<C: Into<Rgb>>(&mut self, cx: isize, cy: isize, radius: usize, color: C) {
+        let r = radius as isize;
+        self.draw_rounded_rect(Rect::new(cx - r, cy - r, 2 * radius + 1, 2 * radius + 1), radius, color);
+    }
+
+    pub fn fill_circle<C: Into<Rgb>>(&mut self, cx: isize, cy: isize, radius: usize, color: C) {
+        let r = radius as isize;
+        self.fill_rounded_rect(Rect::new(cx - r, cy - r, 2 * radius + 1, 2 * radius + 1), radius, color);
+    }
+
+    /// Fill the area around (x, y) that has the same color as that pixel and
+    /// is connected to it horizontally or vertically.
+    pub fn flood_fill<C: Into<Rgb>>(&mut self, x: isize, y: isize, color: C) {
+        let color = color.into();
+        let clip = self.clip();
+        if !clip.contains(x, y) {
+            return;
+        }
+
+        let target = self.pixel(x as usize, y as usize);
+        if target == color {
+            return;
+        }
+
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if !clip.contains(x, y) || self.pixel(x as usize, y as usize) != target {
+                continue;
+            }
+
+            self.plot(x, y, color);
+            stack.extend([(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+
+    /// The buffer as rows of '#' for lit and '.' for dark pixels.
+    fn picture(image: &ImgBuffer) -> Vec<String> {
+        (0..image.height()).map(|y| {
+            (0..image.width())
+                .map(|x| if image.pixel(x, y) == Rgb::default() { '.' } else { '#' })
+                .collect()
+        }).collect()
+    }
+
+    #[test]
+    fn lines_in_every_direction() {
+        let mut image = ImgBuffer::new(5, 3);
+        image.draw_line(0, 0, 4, 2, Color::Red);
+        assert_eq!(picture(&image), vec!["#....", ".##..", "...##"]);
+
+        image.clear();
+        image.draw_line(4, 2, 4, -3, Color::Red);
+        assert_eq!(picture(&image), vec!["....#", "....#", "....#"]);
+    }
+
+    #[test]
+    fn rectangles() {
+        let mut image = ImgBuffer::new(5, 4);
+        image.draw_rect(Rect::new(0, 0, 4, 3), Color::Red);
+        assert_eq!(picture(&image), vec!["####.", "#..#.", "####.", "....."]);
+
+        image.clear();
+        image.fill_rect(Rect::new(3, 2, 10, 10), Color::Red);
+        assert_eq!(picture(&image), vec![".....", ".....", "...##", "...##"]);
+    }
+
+    #[test]
+    fn rounded_corners() {
+        let mut image = ImgBuffer::new(6, 5);
+        image.draw_rounded_rect(Rect::new(0, 0, 6, 5), 1, Color::Red);
+        assert_eq!(picture(&image), vec![".####.", "#....#", "#....#", "#....#", ".####."]);
+
+        image.clear();
+        image.fill_rounded_rect(Rect::new(0, 0, 6, 5), 1, Color::Red);
+        assert_eq!(picture(&image), vec![".####.", "######", "######", "######", ".####."]);
+    }
+
+    #[test]
+    fn circles() {
+        let mut image = ImgBuffer::new(5, 5);
+        image.draw_circle(2, 2, 2, Color::Red);
+        assert_eq!(picture(&image), vec![".###.", "#...#", "#...#", "#...#", ".###."]);
+
+        image.clear();
+        image.fill_circle(2, 2, 2, Color::Red);
+        assert_eq!(picture(&image), vec![".###.", "#####", "#####", "#####", ".###."]);
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_outline_and_clip() {
+        let mut image = ImgBuffer::new(6, 4);
+        image.draw_rect(Rect::new(0, 0, 4, 4), Color::Red);
+        image.flood_fill(1, 1, Color::Blue);
+
+        assert_eq!(image.pixel(2, 2), Color::Blue.into());
+        assert_eq!(image.pixel(5, 0), Rgb::default());
+
+        image.push_clip(Rect::new(0, 0, 5, 4));
+        image.flood_fill(5, 0, Color::Green);
+        assert_eq!(image.pixel(5, 0), Rgb::default());
+        image.flood_fill(4, 0, Color::Green);
+        assert_eq!(image.pixel(4, 3), Color::Green.into());
+        assert_eq!(image.pixel(5, 3), Rgb::default());
+    }
+}
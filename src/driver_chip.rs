@@ -0,0 +1,143 @@
+use serde::Deserialize;
+use crate::pins::Hub75Pins;
+
+/// The shift register chip on the panel. Some newer chips keep the LEDs dark
+/// until their configuration registers have been written.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DriverChip {
+    /// Plain shift registers that need no setup.
+    #[default]
+    Shift,
+    Fm6126a,
+    /// Set up with the FM6126A sequence, as other HUB75 drivers such as
+    /// ESP32-HUB75-MatrixPanel-DMA do.
+    Icn2038s,
+}
+
+impl DriverChip {
+    /// Values for the two configuration registers, one bit per column of
+    /// each 16 bit chip. The first register sets the output current and the
+    /// second turns the outputs on.
+    fn registers(self) -> Option<[&'static str; 2]> {
+        match self {
+            DriverChip::Shift => None,
+            DriverChip::Fm6126a | DriverChip::Icn2038s => Some(["0111111111111111", "0000000001000000"]),
+        }
+    }
+
+    /// Clock the configuration registers into every chip of a chain that is
+    /// `shift_len` columns long.
+    pub fn init<P: Hub75Pins>(self, pins: &mut P, shift_len: usize) {
+        let [reg1, reg2] = match self.registers() {
+            Some(registers) => registers,
+            None => return,
+        };
+
+        pins.output_enable(false);
+
+        // The number of clocks LAT is held high for selects the register
+        write_register(pins, reg1, shift_len, 11);
+        write_register(pins, reg2, shift_len, 12);
+
+        // Clear the data registers so the panel starts out dark
+        pins.set_pins_for_byte(0);
+
+        for _ in 0..shift_len {
+            pins.clock();
+        }
+
+        pins.latch();
+    }
+}
+
+fn write_register<P: Hub75Pins>(pins: &mut P, value: &str, shift_len: usize, latch_clocks: usize) {
+    let bits = value.as_bytes();
+
+    for i in 0..shift_len {
+        let byte = if bits[i % bits.len()] == b'1' { 0b111111 } else { 0 };
+        pins.set_latch(i + latch_clocks >= shift_len);
+        pins.shift_byte(byte);
+    }
+
+    pins.set_latch(false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pins::{Pin, PinEvent, RecordingPins};
+
+    /// Number of clocks LAT was held high for each time it was raised.
+    fn latch_lengths(events: &[PinEvent]) -> Vec<usize> {
+        let mut lengths = vec![];
+        let mut clocks = None;
+
+        for event in events {
+            match (event.pin, event.high) {
+                (Pin::Lat, true) => clocks = Some(0),
+                (Pin::Lat, false) => lengths.extend(clocks.take()),
+                (Pin::Clk, true) => {
+                    if let Some(n) = clocks.as_mut() {
+                        *n += 1;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        lengths
+    }
+
+    #[test]
+    fn shift_registers_need_no_init() {
+        let mut pins = RecordingPins::new(4);
+        DriverChip::Shift.init(&mut pins, 64);
+        assert!(pins.events().is_empty());
+    }
+
+    #[test]
+    fn fm6126a_writes_both_registers() {
+        let mut pins = RecordingPins::new(4);
+        DriverChip::Fm6126a.init(&mut pins, 64);
+
+        let events = pins.events();
+        assert_eq!(latch_lengths(events), vec![11, 12, 0]);
+
+        let clocks = events.iter()
+            .filter(|e| **e == PinEvent { pin: Pin::Clk, high: true })
+            .count();
+        assert_eq!(clocks, 3 * 64);
+    }
+
+    #[test]
+    fn icn2038s_uses_fm6126a_sequence() {
+        let mut fm6126a = RecordingPins::new(4);
+        DriverChip::Fm6126a.init(&mut fm6126a, 64);
+        let mut icn2038s = RecordingPins::new(4);
+        DriverChip::Icn2038s.init(&mut icn2038s, 64);
+
+        assert_eq!(fm6126a.events(), icn2038s.events());
+    }
+
+    #[test]
+    fn short_chains_latch_every_clock() {
+        let mut pins = RecordingPins::new(4);
+        DriverChip::Fm6126a.init(&mut pins, 8);
+        assert_eq!(latch_lengths(pins.events()), vec![8, 8, 0]);
+    }
+
+    #[test]
+    fn register_bits_repeat_every_chip() {
+        let mut pins = RecordingPins::new(4);
+        DriverChip::Fm6126a.init(&mut pins, 32);
+
+        // Data goes high on the second column of each chip in the first register
+        let r0: Vec<bool> = pins.events().iter()
+            .filter(|e| e.pin == Pin::R(0))
+            .map(|e| e.high)
+            .take(4)
+            .collect();
+        assert_eq!(r0, vec![true, false, true, false]);
+    }
+}
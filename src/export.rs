@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::img_buffer::{ImgBuffer, Rgb};
+
+/// Color of an unlit LED in the dot style.
+const DARK_LED: Rgb = Rgb::new(24, 24, 24);
+
+/// How an image is drawn when exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportStyle {
+    /// Output pixels per sign pixel.
+    pub scale: usize,
+    /// Draw every pixel as a round LED on black, like the real sign.
+    pub led_dots: bool,
+}
+
+impl Default for ExportStyle {
+    fn default() -> Self {
+        Self { scale: 1, led_dots: false }
+    }
+}
+
+impl ExportStyle {
+    /// True when the output pixel at (x, y) of a scaled-up sign pixel is
+    /// covered by the LED.
+    fn in_dot(&self, x: usize, y: usize) -> bool {
+        if !self.led_dots {
+            return true;
+        }
+
+        let center = self.scale as f32 / 2.0;
+        let radius = self.scale as f32 * 0.4;
+        let (dx, dy) = (x as f32 + 0.5 - center, y as f32 + 0.5 - center);
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+impl ImgBuffer {
+    /// The image as it would be exported with `style`.
+    pub fn render(&self, style: &ExportStyle) -> ImgBuffer {
+        let scale = style.scale.max(1);
+        let mut output = ImgBuffer::new(self.width() * scale, self.height() * scale);
+
+        for y in 0..output.height() {
+            for x in 0..output.width() {
+                let mut color = self.pixel(x / scale, y / scale);
+                if style.led_dots && color == Rgb::default() {
+                    color = DARK_LED;
+                }
+
+                if style.in_dot(x % scale, y % scale) {
+                    output.set_pixel(x, y, color);
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Raw RGB bytes row by row, as both PPM and PNG store them.
+    fn rgb_bytes(&self) -> Vec<u8> {
+        (0..self.height())
+            .flat_map(|y| (0..self.width()).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let c = self.pixel(x, y);
+                [c.r, c.g, c.b]
+            })
+            .collect()
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width(), self.height())?;
+        writer.write_all(&self.rgb_bytes())
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width() as u32, self.height() as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.rgb_bytes())
+    }
+
+    /// Save the image rendered with `style`, as PNG if the file name ends in
+    /// .png and as PPM otherwise.
+    pub fn save<P: AsRef<Path>>(&self, filename: P, style: &ExportStyle) -> Result<(), Box<dyn Error>> {
+        let filename = filename.as_ref();
+        let image = self.render(style);
+        let file = BufWriter::new(File::create(filename)?);
+
+        if filename.extension().is_some_and(|e| e.eq_ignore_ascii_case("png")) {
+            image.write_png(file)?;
+        } else {
+            image.write_ppm(file)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Read an 8 bit RGB or RGBA PNG, such as a golden image.
+#[cfg(test)]
+fn read_png<P: AsRef<Path>>(filename: P) -> Result<ImgBuffer, Box<dyn Error>> {
+    let mut reader = png::Decoder::new(File::open(filename)?).read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+
+    let channels = match (info.color_type, info.bit_depth) {
+        (png::ColorType::Rgb, png::BitDepth::Eight) => 3,
+        (png::ColorType::Rgba, png::BitDepth::Eight) => 4,
+        other => return Err(format!("Unsupported PNG format {:?}", other).into()),
+    };
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut image = ImgBuffer::new(width, height);
+    for (i, pixel) in data[..info.buffer_size()].chunks(channels).enumerate() {
+        image.set_pixel(i % width, i / width, Rgb::new(pixel[0], pixel[1], pixel[2]));
+    }
+
+    Ok(image)
+}
+
+/// Compare `image` with the reference image `golden/<name>.png`, stored as
+/// PNG so changes show up as images when reviewing. When they differ the
+/// test fails and the actual image is written next to the reference as
+/// `<name>.actual.png` for review. Run the tests with `UPDATE_GOLDEN=1` to
+/// accept the current output as the new reference.
+#[cfg(test)]
+pub fn assert_golden(image: &ImgBuffer, name: &str) {
+    let golden = Path::new("golden").join(format!("{}.png", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all("golden").unwrap();
+        image.save(&golden, &ExportStyle::default()).unwrap();
+        return;
+    }
+
+    let reference = read_png(&golden)
+        .unwrap_or_else(|e| panic!("Can't read {}, run with UPDATE_GOLDEN=1 to create it: {}", golden.display(), e));
+
+    let same_size = (reference.width(), reference.height()) == (image.width(), image.height());
+    let differences = if same_size {
+        (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| reference.pixel(x, y) != image.pixel(x, y))
+            .count()
+    } else {
+        image.width() * image.height()
+    };
+
+    if differences > 0 {
+        let actual = golden.with_extension("actual.png");
+        image.save(&actual, &ExportStyle { scale: 8, led_dots: true }).unwrap();
+        panic!("{} pixels differ from {}, see {}", differences, golden.display(), actual.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+
+    #[test]
+    fn ppm_has_header_and_pixels() {
+        let mut image = ImgBuffer::new(2, 1);
+        image.set_pixel(1, 0, Rgb::new(1, 2, 3));
+
+        let mut bytes = vec![];
+        image.write_ppm(&mut bytes).unwrap();
+        assert_eq!(bytes, b"P6\n2 1\n255\n\0\0\0\x01\x02\x03");
+    }
+
+    #[test]
+    fn png_has_signature() {
+        let mut bytes = vec![];
+        ImgBuffer::new(2, 2).write_png(&mut bytes).unwrap();
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let mut image = ImgBuffer::new(3, 2);
+        image.set_pixel(2, 1, Rgb::new(1, 2, 3));
+
+        let filename = std::env::temp_dir().join(format!("export-{}.png", std::process::id()));
+        image.save(&filename, &ExportStyle::default()).unwrap();
+        let read = read_png(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!((read.width(), read.height()), (3, 2));
+        assert_eq!(read.pixel(2, 1), Rgb::new(1, 2, 3));
+        assert_eq!(read.pixel(0, 0), Rgb::default());
+    }
+
+    #[test]
+    fn pixels_can_be_scaled_up() {
+        let mut image = ImgBuffer::new(2, 1);
+        image.set_pixel(1, 0, Color::Red);
+
+        let output = image.render(&ExportStyle { scale: 3, led_dots: false });
+        assert_eq!((output.width(), output.height()), (6, 3));
+        assert_eq!(output.pixel(2, 2), Rgb::default());
+        assert_eq!(output.pixel(3, 0), Color::Red.into());
+        assert_eq!(output.pixel(5, 2), Color::Red.into());
+    }
+
+    #[test]
+    fn led_dots_leave_gaps() {
+        let mut image = ImgBuffer::new(1, 1);
+        image.set_pixel(0, 0, Color::Red);
+
+        let output = image.render(&ExportStyle { scale: 5, led_dots: true });
+        assert_eq!(output.pixel(2, 2), Color::Red.into());
+        assert_eq!(output.pixel(0, 0), Rgb::default());
+
+        let output = ImgBuffer::new(1, 1).render(&ExportStyle { scale: 5, led_dots: true });
+        assert_eq!(output.pixel(2, 2), DARK_LED);
+    }
+}
@@ -1,17 +1,33 @@
 use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::error::Error;
+use std::path::Path;
 use serde::Deserialize;
+use crate::bdf;
 use crate::ppm::PPM;
 
+/// Blank columns drawn between characters of TOML fonts, whose glyphs have
+/// no spacing of their own.
+const CHAR_SPACING: usize = 1;
+
 pub struct Font {
     char_map: HashMap::<char, FontChar>,
+    /// Blank columns added after every character's advance.
+    char_spacing: usize,
+    line_height: usize,
 }
 
+/// A glyph bitmap and where it sits relative to the pen position, which is
+/// the top left corner of the line.
 pub struct FontChar {
     width: usize,
     height: usize,
     data: Vec<u8>,
+    x_offset: isize,
+    y_offset: isize,
+    /// Distance to the next character's pen position, before the font's
+    /// character spacing.
+    advance: usize,
 }
 
 #[derive(Deserialize, Debug)]
@@ -30,7 +46,26 @@ struct CharConfig {
 }
 
 impl Font {
+    /// Build a font from its glyphs. The line height is that of the tallest
+    /// glyph when not given.
+    pub fn from_chars(char_map: HashMap<char, FontChar>, char_spacing: usize, line_height: Option<usize>) -> Self {
+        let line_height = line_height.unwrap_or_else(|| {
+            char_map.values()
+                .map(|c| (c.y_offset + c.height as isize).max(0) as usize)
+                .max()
+                .unwrap_or(0)
+        });
+
+        Self { char_map, char_spacing, line_height }
+    }
+
+    /// Load a BDF bitmap font if the file name ends in .bdf, otherwise a
+    /// TOML glyph map into a PPM image.
     pub fn load(filename: &str) -> Result<Self, Box<dyn Error>> {
+        if Path::new(filename).extension().is_some_and(|e| e.eq_ignore_ascii_case("bdf")) {
+            return Self::load_bdf(filename);
+        }
+
         let config_text = read_to_string(filename)?;
         let config: FontConfig = toml::from_str(&config_text)?;
         
@@ -64,9 +99,12 @@ impl Font {
             char_map.insert(*c, font_char);
         }
         
-        Ok(Font {
-            char_map
-        })
+        Ok(Font::from_chars(char_map, CHAR_SPACING, None))
+    }
+
+    pub fn load_bdf(filename: &str) -> Result<Self, Box<dyn Error>> {
+        let text = read_to_string(filename)?;
+        Ok(bdf::parse(&text)?)
     }
     
     pub fn len(&self) -> usize {
@@ -76,15 +114,59 @@ impl Font {
     pub fn char(&self, c: &char) -> Option<&FontChar> {
         self.char_map.get(c)
     }
+
+    /// Distance between lines of text before any extra line spacing.
+    pub fn line_height(&self) -> usize {
+        self.line_height
+    }
+
+    pub fn char_spacing(&self) -> usize {
+        self.char_spacing
+    }
+
+    /// Width of `text` in pixels as `ImgBuffer::draw_str` draws it, from the
+    /// characters' advances and the spacing between them. Characters missing
+    /// from the font take no space.
+    pub fn text_width(&self, text: &str) -> usize {
+        text.chars()
+            .filter_map(|c| self.char(&c))
+            .map(|c| c.advance() + self.char_spacing)
+            .sum::<usize>()
+            .saturating_sub(self.char_spacing)
+    }
 }
 
 impl FontChar {
+    /// A glyph drawn at the pen position that advances by its width.
     pub fn new(width: usize, height: usize, data: Vec<u8>) -> Self {
         Self {
             width, height, data,
+            x_offset: 0,
+            y_offset: 0,
+            advance: width,
         }
     }
 
+    /// A glyph with its bounding box at (`x_offset`, `y_offset`) from the pen
+    /// position, as in BDF fonts.
+    pub fn with_metrics(width: usize, height: usize, data: Vec<u8>, x_offset: isize, y_offset: isize, advance: usize) -> Self {
+        Self {
+            width, height, data, x_offset, y_offset, advance,
+        }
+    }
+
+    pub fn x_offset(&self) -> isize {
+        self.x_offset
+    }
+
+    pub fn y_offset(&self) -> isize {
+        self.y_offset
+    }
+
+    pub fn advance(&self) -> usize {
+        self.advance
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -132,6 +214,15 @@ mod tests {
         ]);        
     }
 
+    #[test]
+    fn text_width_includes_spacing() {
+        let font = Font::load("fonts/metronorth.toml").unwrap();
+        assert_eq!(font.text_width("I"), 1);
+        assert_eq!(font.text_width("AI"), 6);
+        assert_eq!(font.text_width("A\u{2603}I"), 6);
+        assert_eq!(font.text_width(""), 0);
+    }
+
     #[test]
     fn it_can_load_metronorth() {
         let font = Font::load("fonts/metronorth.toml").unwrap();
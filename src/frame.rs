@@ -0,0 +1,212 @@
+use std::sync::Arc;
+use crate::color_correction::{ColorCorrection, ColorTables};
+use crate::img_buffer::{ImgBuffer, Rgb};
+use crate::layout::PanelLayout;
+use crate::transform::DisplayTransform;
+
+/// An image converted into the bit planes a panel shifts out, ordered by row
+/// address, then bit plane, then shift position. Each byte holds the upper
+/// RGB bits in bits 0-2 and the lower RGB bits in bits 3-5.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    data: Vec<u8>,
+    depth: usize,
+    shift_len: usize,
+}
+
+/// Image pixels for the upper and lower half of every shifted column, or
+/// None where nothing is drawn.
+type ShiftMap = Arc<[[Option<(usize, usize)>; 2]]>;
+
+/// Everything needed to convert images into `Frame`s for one panel. It is
+/// cheap to clone, so a render thread can convert images while the panel
+/// itself belongs to the strobe thread.
+#[derive(Clone)]
+pub struct FrameFormat {
+    /// Size of the whole sign as mounted.
+    physical: (usize, usize),
+    /// Physical pixels for the upper and lower half of every shifted column,
+    /// indexed by row address then shift position.
+    layout_map: Arc<[[(usize, usize); 2]]>,
+    transform: DisplayTransform,
+    /// Size of the images drawn, after undoing `transform`.
+    width: usize,
+    height: usize,
+    /// `layout_map` with `transform` undone.
+    shift_map: ShiftMap,
+    shift_len: usize,
+    depth: usize,
+    correction: ColorCorrection,
+    /// Built from `correction` and `depth` whenever either changes.
+    tables: Arc<ColorTables>,
+}
+
+impl Frame {
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Shift data for one bit plane of a row address, where plane 0 is the
+    /// least significant bit.
+    pub fn row(&self, row: usize, plane: usize) -> &[u8] {
+        let start = (row * self.depth + plane) * self.shift_len;
+        &self.data[start..start + self.shift_len]
+    }
+}
+
+impl FrameFormat {
+    /// Frames for `layout` driven at `scan_rows` row addresses, showing
+    /// `depth` bits per channel after color correction.
+    pub fn new(layout: &PanelLayout, scan_rows: usize, depth: usize, correction: ColorCorrection) -> Self {
+        assert!((1..=8).contains(&depth), "Color depth must be 1 to 8 bits");
+
+        let mut format = Self {
+            physical: (layout.width(), layout.height()),
+            layout_map: layout.shift_map(scan_rows).into(),
+            transform: DisplayTransform::default(),
+            width: 0,
+            height: 0,
+            shift_map: Arc::new([]),
+            shift_len: layout.shift_len(),
+            depth,
+            correction,
+            tables: Arc::new(ColorTables::new(&correction, depth)),
+        };
+        format.set_transform(DisplayTransform::default());
+        format
+    }
+
+    /// Width of the images to convert.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the images to convert.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Set how images are placed on the sign as mounted. The image size
+    /// swaps for 90 and 270 degree rotations.
+    pub fn set_transform(&mut self, transform: DisplayTransform) {
+        let (width, height) = self.physical;
+        (self.width, self.height) = transform.logical_size(width, height);
+        self.transform = transform;
+        self.shift_map = self.layout_map.iter()
+            .map(|pair| pair.map(|(x, y)| transform.to_logical(x, y, width, height)))
+            .collect();
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        assert!((1..=8).contains(&depth), "Color depth must be 1 to 8 bits");
+        self.depth = depth;
+        self.tables = Arc::new(ColorTables::new(&self.correction, depth));
+    }
+
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.correction = correction;
+        self.tables = Arc::new(ColorTables::new(&correction, self.depth));
+    }
+
+    pub fn convert(&self, img_buffer: &ImgBuffer) -> Frame {
+        let mut frame = Frame { data: vec![], depth: 0, shift_len: 0 };
+        self.convert_into(img_buffer, &mut frame);
+        frame
+    }
+
+    /// Convert an image, reusing the memory of a frame that is no longer
+    /// displayed.
+    pub fn convert_into(&self, img_buffer: &ImgBuffer, frame: &mut Frame) {
+        assert_eq!((img_buffer.width(), img_buffer.height()), (self.width, self.height),
+                   "Image size doesn't match the panel layout");
+
+        let depth = self.depth;
+        let shift_len = self.shift_len;
+
+        frame.depth = depth;
+        frame.shift_len = shift_len;
+        frame.data.clear();
+        frame.data.resize(self.shift_map.len() * depth, 0);
+
+        for (row, row_map) in self.shift_map.chunks(shift_len).enumerate() {
+            for (col, [upper, lower]) in row_map.iter().enumerate() {
+                let color = |pixel: Option<(usize, usize)>| {
+                    pixel.map_or(Rgb::default(), |(x, y)| self.tables.apply(img_buffer.pixel(x, y)))
+                };
+                let (upper, lower) = (color(*upper), color(*lower));
+
+                for plane in 0..depth {
+                    let bits = |c: Rgb| {
+                        ((c.r >> plane) & 1) | ((c.g >> plane) & 1) << 1 | ((c.b >> plane) & 1) << 2
+                    };
+
+                    frame.data[(row * depth + plane) * shift_len + col] = bits(upper) | bits(lower) << 3;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+    use crate::layout::Rotation;
+
+    #[test]
+    fn color_is_split_into_bit_planes() {
+        let mut image = ImgBuffer::new(4, 4);
+        image.set_pixel(1, 0, Rgb::new(0xff, 0x50, 0x00));
+        image.set_pixel(1, 2, Color::Blue);
+
+        let layout = PanelLayout::single(4, 4);
+        let frame = FrameFormat::new(&layout, 2, 4, ColorCorrection::LINEAR).convert(&image);
+
+        let planes: Vec<u8> = (0..4).map(|plane| frame.row(0, plane)[1]).collect();
+
+        // Green is 0b0101 in the top four bits, blue fills the lower half
+        assert_eq!(planes, vec![
+            0b100011,
+            0b100001,
+            0b100011,
+            0b100001,
+        ]);
+    }
+
+    #[test]
+    fn rows_follow_the_scan() {
+        let mut image = ImgBuffer::new(2, 4);
+        image.set_pixel(0, 1, Color::White);
+        image.set_pixel(1, 1, Color::Red);
+        image.set_pixel(1, 3, Color::Teal);
+
+        let layout = PanelLayout::single(2, 4);
+        let frame = FrameFormat::new(&layout, 2, 1, ColorCorrection::LINEAR).convert(&image);
+
+        assert_eq!(frame.row(0, 0), &[0, 0]);
+        assert_eq!(frame.row(1, 0), &[0b000111, 0b110001]);
+    }
+
+    #[test]
+    fn transform_is_applied_on_output() {
+        let mut format = FrameFormat::new(&PanelLayout::single(4, 2), 1, 1, ColorCorrection::LINEAR);
+        format.set_transform(DisplayTransform {
+            rotation: Rotation::Cw180,
+            offset_x: -1,
+            ..DisplayTransform::default()
+        });
+
+        let mut image = ImgBuffer::new(4, 2);
+        image.set_pixel(0, 1, Color::Red);
+        image.set_pixel(3, 0, Color::Green);
+        let frame = format.convert(&image);
+
+        // Red turns to the top right and moves one left, green falls off
+        // the left edge
+        assert_eq!(frame.row(0, 0), &[0, 0, 0b001, 0]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError};
+use crate::frame::Frame;
+
+/// Render side of a frame handoff. Frames are sent whole, so the strobe
+/// thread never sees one that is half drawn.
+pub struct FrameSender {
+    frames: SyncSender<Frame>,
+    spares: Receiver<Frame>,
+}
+
+/// Strobe side of a frame handoff. It holds the frame on display and picks up
+/// new ones without ever blocking.
+pub struct FrameReceiver {
+    frames: Receiver<Frame>,
+    spares: Sender<Frame>,
+    current: Frame,
+}
+
+/// Create a handoff that starts out displaying `initial`.
+///
+/// Up to three frames are in play: the one on display, one waiting to be
+/// swapped in, and one being drawn by the render thread.
+pub fn frame_swap(initial: Frame) -> (FrameSender, FrameReceiver) {
+    let (frames_tx, frames_rx) = sync_channel(1);
+    let (spares_tx, spares_rx) = channel();
+
+    let sender = FrameSender {
+        frames: frames_tx,
+        spares: spares_rx,
+    };
+
+    let receiver = FrameReceiver {
+        frames: frames_rx,
+        spares: spares_tx,
+        current: initial,
+    };
+
+    (sender, receiver)
+}
+
+impl FrameSender {
+    /// Queue a frame to be displayed at the next frame boundary. Blocks while
+    /// a previously sent frame is still waiting, and fails once the receiver
+    /// is gone.
+    pub fn send(&self, frame: Frame) -> Result<(), Frame> {
+        self.frames.send(frame).map_err(|e| e.0)
+    }
+
+    /// A frame that has been taken off the display, so its memory can be
+    /// reused for drawing the next one.
+    pub fn spare(&self) -> Option<Frame> {
+        self.spares.try_recv().ok()
+    }
+}
+
+impl FrameReceiver {
+    pub fn current(&self) -> &Frame {
+        &self.current
+    }
+
+    /// Swap in the newest queued frame, if there is one. Call this only at a
+    /// frame boundary. Returns true when the displayed frame changed.
+    pub fn swap(&mut self) -> bool {
+        match self.frames.try_recv() {
+            Ok(frame) => {
+                let old = std::mem::replace(&mut self.current, frame);
+                // The sender may have stopped; the old frame is just dropped
+                let _ = self.spares.send(old);
+                true
+            },
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::color_correction::ColorCorrection;
+    use crate::frame::FrameFormat;
+    use crate::img_buffer::{Color, ImgBuffer};
+    use crate::layout::PanelLayout;
+
+    fn frame_with_pixel(format: &FrameFormat, color: Color) -> Frame {
+        let mut image = ImgBuffer::new(2, 2);
+        image.set_pixel(0, 0, color);
+        format.convert(&image)
+    }
+
+    #[test]
+    fn frames_are_swapped_on_request() {
+        let format = FrameFormat::new(&PanelLayout::single(2, 2), 1, 1, ColorCorrection::LINEAR);
+        let (sender, mut receiver) = frame_swap(format.convert(&ImgBuffer::new(2, 2)));
+
+        assert!(!receiver.swap());
+        assert_eq!(receiver.current().row(0, 0)[0], 0);
+
+        sender.send(frame_with_pixel(&format, Color::Red)).unwrap();
+        assert_eq!(receiver.current().row(0, 0)[0], 0);
+
+        assert!(receiver.swap());
+        assert_eq!(receiver.current().row(0, 0)[0], 0b001);
+
+        // The frame taken off the display comes back for reuse
+        assert!(sender.spare().is_some());
+        assert!(sender.spare().is_none());
+    }
+
+    #[test]
+    fn render_thread_hands_off_frames() {
+        let format = FrameFormat::new(&PanelLayout::single(2, 2), 1, 1, ColorCorrection::LINEAR);
+        let (sender, mut receiver) = frame_swap(format.convert(&ImgBuffer::new(2, 2)));
+
+        let render = thread::spawn({
+            let format = format.clone();
+            move || {
+                for color in [Color::Red, Color::Green, Color::Blue] {
+                    sender.send(frame_with_pixel(&format, color)).unwrap();
+                }
+            }
+        });
+
+        let mut seen = vec![];
+        while seen.len() < 3 {
+            if receiver.swap() {
+                seen.push(receiver.current().row(0, 0)[0]);
+            }
+        }
+
+        render.join().unwrap();
+        assert_eq!(seen, vec![0b001, 0b010, 0b100]);
+    }
+
+    #[test]
+    fn sending_fails_once_the_receiver_is_gone() {
+        let format = FrameFormat::new(&PanelLayout::single(2, 2), 1, 1, ColorCorrection::LINEAR);
+        let (sender, receiver) = frame_swap(format.convert(&ImgBuffer::new(2, 2)));
+        drop(receiver);
+
+        assert!(sender.send(frame_with_pixel(&format, Color::Red)).is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use crate::pins::{Hub75PinNums, Hub75Pins};
+
+// Register offsets in 32 bit words from the start of the BCM283x GPIO block
+const GPFSEL0: usize = 0;
+const GPSET0: usize = 7;
+const GPCLR0: usize = 10;
+
+const BLOCK_SIZE: usize = 4096;
+
+/// Drives the panel by writing the GPIO set and clear registers through
+/// `/dev/gpiomem`, so every data pin and the clock can change in one or two
+/// writes. Works on the BCM283x and BCM2711 based boards (Pi 1 to 4); only
+/// GPIO 0-31 can be used.
+pub struct GpioMemPins<const LC: usize> {
+    regs: *mut u32,
+    mapped: bool,
+    lines: [u32; LC],
+    rgb: [u32; 6],
+    rgb_mask: u32,
+    clk: u32,
+    lat: u32,
+    oe: u32,
+    /// Times each clock edge is written, to slow the clock down for panels
+    /// that can't keep up.
+    slowdown: usize,
+}
+
+// The mapping belongs to this struct alone, so it can move between threads
+unsafe impl<const LC: usize> Send for GpioMemPins<LC> {}
+
+fn bit(pin: u8) -> io::Result<u32> {
+    if pin < 32 {
+        Ok(1 << pin)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, format!("GPIO {} is out of range", pin)))
+    }
+}
+
+impl<const LC: usize> GpioMemPins<LC> {
+    pub fn from_pin_nums(pin_nums: &Hub75PinNums<LC>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_SYNC)
+            .open("/dev/gpiomem")?;
+
+        let regs = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                BLOCK_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if regs == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        match unsafe { Self::with_registers(regs as *mut u32, pin_nums) } {
+            Ok(mut pins) => {
+                pins.mapped = true;
+                Ok(pins)
+            },
+            Err(e) => {
+                unsafe {
+                    libc::munmap(regs, BLOCK_SIZE);
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Set up the pins on an already mapped GPIO register block.
+    ///
+    /// # Safety
+    ///
+    /// `regs` must point to at least `BLOCK_SIZE` bytes that stay valid for
+    /// the life of the returned value.
+    unsafe fn with_registers(regs: *mut u32, pin_nums: &Hub75PinNums<LC>) -> io::Result<Self> {
+        let mut lines = [0; LC];
+        for (mask, pin) in lines.iter_mut().zip(pin_nums.lines) {
+            *mask = bit(pin)?;
+        }
+
+        let rgb_pins = [
+            pin_nums.r[0], pin_nums.g[0], pin_nums.b[0],
+            pin_nums.r[1], pin_nums.g[1], pin_nums.b[1],
+        ];
+        let mut rgb = [0; 6];
+        for (mask, pin) in rgb.iter_mut().zip(rgb_pins) {
+            *mask = bit(pin)?;
+        }
+
+        let mut pins = Self {
+            regs,
+            mapped: false,
+            lines,
+            rgb,
+            rgb_mask: rgb.iter().fold(0, |acc, m| acc | m),
+            clk: bit(pin_nums.clk)?,
+            lat: bit(pin_nums.lat)?,
+            oe: bit(pin_nums.oe)?,
+            slowdown: 1,
+        };
+
+        // Start with every pin low apart from OE, which is active low, so
+        // the panel stays dark
+        let all = pins.lines.iter().fold(pins.rgb_mask | pins.clk | pins.lat | pins.oe, |acc, m| acc | m);
+        pins.set(pins.oe);
+        pins.clear(all & !pins.oe);
+
+        for pin in (0..32).filter(|p| all & (1 << p) != 0) {
+            pins.set_output(pin);
+        }
+
+        Ok(pins)
+    }
+
+    pub fn set_slowdown(&mut self, slowdown: usize) {
+        self.slowdown = slowdown.max(1);
+    }
+
+    fn set_output(&mut self, pin: usize) {
+        let fsel = GPFSEL0 + pin / 10;
+        let shift = (pin % 10) * 3;
+
+        unsafe {
+            let reg = self.regs.add(fsel);
+            let value = ptr::read_volatile(reg);
+            ptr::write_volatile(reg, (value & !(0b111 << shift)) | (0b001 << shift));
+        }
+    }
+
+    fn set(&mut self, mask: u32) {
+        unsafe { ptr::write_volatile(self.regs.add(GPSET0), mask) }
+    }
+
+    fn clear(&mut self, mask: u32) {
+        unsafe { ptr::write_volatile(self.regs.add(GPCLR0), mask) }
+    }
+
+    fn rgb_bits(&self, byte: u8) -> u32 {
+        self.rgb.iter()
+            .enumerate()
+            .filter(|(i, _)| byte & (1 << i) != 0)
+            .fold(0, |acc, (_, m)| acc | m)
+    }
+}
+
+impl<const LC: usize> Drop for GpioMemPins<LC> {
+    fn drop(&mut self) {
+        if self.mapped {
+            unsafe {
+                libc::munmap(self.regs as *mut libc::c_void, BLOCK_SIZE);
+            }
+        }
+    }
+}
+
+impl<const LC: usize> Hub75Pins for GpioMemPins<LC> {
+    fn set_pins_for_byte(&mut self, byte: u8) {
+        let on = self.rgb_bits(byte);
+        self.clear(self.rgb_mask & !on);
+        self.set(on);
+    }
+
+    fn clock(&mut self) {
+        for _ in 0..self.slowdown {
+            self.set(self.clk);
+        }
+
+        self.clear(self.clk);
+    }
+
+    fn latch(&mut self) {
+        for _ in 0..self.slowdown {
+            self.set(self.lat);
+        }
+
+        self.clear(self.lat);
+    }
+
+    fn set_latch(&mut self, high: bool) {
+        if high {
+            self.set(self.lat);
+        } else {
+            self.clear(self.lat);
+        }
+    }
+
+    fn select_row(&mut self, row: usize) {
+        let (high, low) = self.lines.iter()
+            .enumerate()
+            .fold((0, 0), |(high, low), (i, m)| {
+                if row & (1 << i) != 0 { (high | m, low) } else { (high, low | m) }
+            });
+
+        self.clear(low);
+        self.set(high);
+    }
+
+    fn line_count(&self) -> usize {
+        LC
+    }
+
+    fn output_enable(&mut self, enabled: bool) {
+        if enabled {
+            self.clear(self.oe);
+        } else {
+            self.set(self.oe);
+        }
+    }
+
+    fn blank(&mut self) {
+        let lines = self.lines.iter().fold(0, |acc, m| acc | m);
+        self.set(self.oe);
+        self.clear(lines);
+    }
+
+    /// Clock low and the new data go out in one pair of writes, then the
+    /// rising edge clocks it in.
+    fn shift_byte(&mut self, byte: u8) {
+        let on = self.rgb_bits(byte);
+        self.clear((self.rgb_mask & !on) | self.clk);
+        self.set(on);
+
+        for _ in 0..self.slowdown {
+            self.set(self.clk);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pin_nums() -> Hub75PinNums<5> {
+        Hub75PinNums {
+            lines: [22, 26, 27, 20, 24],
+            r: [5, 12],
+            g: [13, 16],
+            b: [6, 23],
+            clk: 17,
+            oe: 4,
+            lat: 21,
+        }
+    }
+
+    #[test]
+    fn pins_are_made_outputs() {
+        let mut regs = vec![0xffff_ffffu32; BLOCK_SIZE / 4];
+        let _pins = unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &pin_nums()).unwrap() };
+
+        // GPIO 4 and 5 in GPFSEL0, GPIO 17 in GPFSEL1
+        assert_eq!((regs[0] >> 12) & 0b111, 0b001);
+        assert_eq!((regs[0] >> 15) & 0b111, 0b001);
+        assert_eq!((regs[1] >> 21) & 0b111, 0b001);
+        // GPIO 3 is left alone
+        assert_eq!((regs[0] >> 9) & 0b111, 0b111);
+    }
+
+    #[test]
+    fn shift_byte_writes_whole_words() {
+        let mut regs = vec![0u32; BLOCK_SIZE / 4];
+        let mut pins = unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &pin_nums()).unwrap() };
+
+        // R1 and B2
+        pins.shift_byte(0b100001);
+        drop(pins);
+
+        assert_eq!(regs[GPSET0], 1 << 17);
+        assert_eq!(regs[GPCLR0], (1 << 13) | (1 << 6) | (1 << 12) | (1 << 16) | (1 << 17));
+    }
+
+    #[test]
+    fn select_row_sets_and_clears_lines() {
+        let mut regs = vec![0u32; BLOCK_SIZE / 4];
+        let mut pins = unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &pin_nums()).unwrap() };
+
+        pins.select_row(0b10011);
+        drop(pins);
+
+        assert_eq!(regs[GPSET0], (1 << 22) | (1 << 26) | (1 << 24));
+        assert_eq!(regs[GPCLR0], (1 << 27) | (1 << 20));
+    }
+
+    #[test]
+    fn blank_disables_output() {
+        let mut regs = vec![0u32; BLOCK_SIZE / 4];
+        let mut pins = unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &pin_nums()).unwrap() };
+
+        pins.blank();
+        drop(pins);
+
+        assert_eq!(regs[GPSET0], 1 << 4);
+        assert_eq!(regs[GPCLR0], (1 << 22) | (1 << 26) | (1 << 27) | (1 << 20) | (1 << 24));
+    }
+
+    #[test]
+    fn output_starts_disabled() {
+        let mut regs = vec![0u32; BLOCK_SIZE / 4];
+        let pins = unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &pin_nums()).unwrap() };
+        drop(pins);
+
+        assert_eq!(regs[GPSET0], 1 << 4);
+        assert_eq!(regs[GPCLR0] & (1 << 4), 0);
+    }
+
+    #[test]
+    fn pins_above_31_are_rejected() {
+        let mut regs = vec![0u32; BLOCK_SIZE / 4];
+        let mut nums = pin_nums();
+        nums.clk = 40;
+
+        assert!(unsafe { GpioMemPins::with_registers(regs.as_mut_ptr(), &nums) }.is_err());
+    }
+}
@@ -1,185 +1,434 @@
-use std::time::Duration;
-use rppal::gpio::{Gpio, OutputPin};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
 use spin_sleep::sleep;
+use crate::brightness::Brightness;
+use crate::color_correction::ColorCorrection;
+use crate::driver_chip::DriverChip;
+use crate::frame::{Frame, FrameFormat};
 use crate::img_buffer::ImgBuffer;
+use crate::layout::PanelLayout;
+use crate::pins::Hub75Pins;
+use crate::transform::DisplayTransform;
 
-pub struct Hub75PinNums<const LC: usize> {
-    pub lines: [u8; LC],
-	pub r: [u8; 2],
-	pub g: [u8; 2],
-	pub b: [u8; 2],
-    pub clk: u8,
-    pub lat: u8,
-    pub oe: u8,
+/// Fraction of the panel rows addressed at once. A 1/16 scan panel has 16
+/// row addresses, each lighting one row in the upper and one in the lower
+/// half. In config files it is written as the number of row addresses.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(try_from = "usize")]
+pub enum ScanRate {
+	Quarter,
+	Eighth,
+	#[default]
+	Sixteenth,
+	ThirtySecond,
 }
 
-pub struct Hub75Pins<const LC: usize> {
-    pub lines: [OutputPin; LC],
-	pub r: [OutputPin; 2],
-	pub g: [OutputPin; 2],
-	pub b: [OutputPin; 2],
-    pub clk: OutputPin,
-    pub lat: OutputPin,
-    pub oe: OutputPin,
+impl ScanRate {
+	/// Number of distinct row addresses.
+	pub fn row_count(self) -> usize {
+		match self {
+			ScanRate::Quarter => 4,
+			ScanRate::Eighth => 8,
+			ScanRate::Sixteenth => 16,
+			ScanRate::ThirtySecond => 32,
+		}
+	}
+
+	/// Number of address lines (A, B, C, ...) needed to select every row.
+	pub fn address_lines(self) -> usize {
+		self.row_count().trailing_zeros() as usize
+	}
 }
 
-impl<const LC: usize> Hub75Pins<LC> {
-	pub fn from_pin_nums(pin_nums: &Hub75PinNums<LC>) -> Hub75Pins<LC> {
-		let gpio = Gpio::new().unwrap();
-
-		let lines: [OutputPin; LC] = pin_nums.lines.into_iter().map(|x|
-			 gpio.get(x).expect("Couldn't get pin").into_output_low()
-		).collect::<Vec<OutputPin>>().try_into().unwrap();
-		
-		let r: [OutputPin; 2] = pin_nums.r.into_iter().map(|x|
-			 gpio.get(x).unwrap().into_output_low()
-		).collect::<Vec<OutputPin>>().try_into().unwrap();
-
-		let g: [OutputPin; 2] = pin_nums.g.into_iter().map(|x|
-			 gpio.get(x).unwrap().into_output_low()
-		).collect::<Vec<OutputPin>>().try_into().unwrap();
-
-		let b: [OutputPin; 2] = pin_nums.b.into_iter().map(|x|
-			 gpio.get(x).unwrap().into_output_low()
-		).collect::<Vec<OutputPin>>().try_into().unwrap();
-
-		Hub75Pins {
-			lines, r, g, b,
-			clk: gpio.get(pin_nums.clk).unwrap().into_output_low(),
-			lat: gpio.get(pin_nums.lat).unwrap().into_output_low(),
-			oe: gpio.get(pin_nums.oe).unwrap().into_output_low(),
+impl TryFrom<usize> for ScanRate {
+	type Error = String;
+
+	fn try_from(rows: usize) -> Result<Self, Self::Error> {
+		match rows {
+			4 => Ok(ScanRate::Quarter),
+			8 => Ok(ScanRate::Eighth),
+			16 => Ok(ScanRate::Sixteenth),
+			32 => Ok(ScanRate::ThirtySecond),
+			_ => Err(format!("There is no 1/{} scan rate", rows)),
 		}
 	}
 }
 
-pub struct Hub75Panel<const LC: usize> {
-	cols: usize,
-	rows: usize,
-	pins: Hub75Pins<LC>,
-	active_row: usize,
+/// Dark time around row switches that keeps a faint copy of one row from
+/// showing on the next. Every delay is in nanoseconds and zero disables it;
+/// the right values depend on the panel model.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DeadTime {
+	/// Keep the outputs off this long before the row address changes.
+	pub blank_ns: u64,
+	/// Wait this long after the address changes before lighting the row.
+	pub settle_ns: u64,
+	/// Switch the address before latching the new data and wait this long,
+	/// so the row drivers have charged before the columns change.
+	pub precharge_ns: u64,
 }
 
-impl<const LC: usize> Hub75Panel<LC> {
-	pub fn new(cols: usize, rows: usize, pins: Hub75PinNums<LC>) -> Hub75Panel<LC> {
-		let pins = Hub75Pins::from_pin_nums(&pins);
-		Hub75Panel {cols, rows, pins, active_row: 0}
+fn pause(ns: u64) {
+	if ns > 0 {
+		sleep(Duration::from_nanos(ns));
 	}
+}
 
-	pub fn strobe_row(&mut self, img_buffer: &ImgBuffer) {
-		let row_data = img_buffer.get_display_row(self.active_row);
+pub struct Hub75Panel<P: Hub75Pins> {
+	scan: ScanRate,
+	pins: P,
+	active_row: usize,
+	lsb_time: Duration,
+	dead_time: DeadTime,
+	brightness: Brightness,
+	format: FrameFormat,
+}
 
-		for i in 0..self.cols {
-			self.set_pins_for_byte(row_data[i]);
-			self.clock();
+impl<P: Hub75Pins> Hub75Panel<P> {
+	pub fn new(layout: PanelLayout, scan: ScanRate, driver: DriverChip, mut pins: P) -> Hub75Panel<P> {
+		if let Err(e) = layout.validate() {
+			panic!("Invalid panel layout: {}", e);
 		}
+		assert_eq!(layout.panel_rows, 2 * scan.row_count() * layout.mapping.factor(),
+			"Panel rows don't match the scan rate");
+		assert!(pins.line_count() >= scan.address_lines(),
+			"{:?} scan needs {} address lines", scan, scan.address_lines());
 
-		self.pins.oe.set_high();
-		self.latch();
-		self.select_row(self.active_row);
-		self.pins.oe.set_low();
+		let format = FrameFormat::new(&layout, scan.row_count(), 4, ColorCorrection::default());
+		driver.init(&mut pins, layout.shift_len());
 
-		self.active_row = (self.active_row + 1) % 16;
+		Hub75Panel {
+			scan, pins, format,
+			active_row: 0,
+			lsb_time: Duration::from_micros(10),
+			dead_time: DeadTime::default(),
+			brightness: Brightness::new(100),
+		}
 	}
 
-	fn set_pins_for_byte(&mut self, byte: u8) {
-		self.pins.r[0].write((byte & 1 != 0).into());
-		self.pins.g[0].write((byte & 2 != 0).into());
-		self.pins.b[0].write((byte & 4 != 0).into());
-		self.pins.r[1].write((byte & 8 != 0).into());
-		self.pins.g[1].write((byte & 16 != 0).into());
-		self.pins.b[1].write((byte & 32 != 0).into());
+	/// Set how many bits per color channel are displayed.
+	pub fn set_depth(&mut self, depth: usize) {
+		self.format.set_depth(depth);
 	}
 
-	pub fn select_row(&mut self, row: usize) {
-		for line in self.pins.lines.iter_mut() {
-			line.set_low();
-		}
+	/// Set the gamma curve and white balance. The lookup tables are rebuilt
+	/// here rather than for every frame.
+	pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+		self.format.set_color_correction(correction);
+	}
 
-		match row {
-			0 => {},
-			1 => {
-				self.pins.lines[0].set_high();
-			},
-			2 => {
-				self.pins.lines[1].set_high();
-			},
-			3 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[1].set_high();
-			},
-			4 => {
-				self.pins.lines[2].set_high();
-			},
-			5 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[2].set_high();
-			},
-			6 => {
-				self.pins.lines[1].set_high();
-				self.pins.lines[2].set_high();
-			},
-			7 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[1].set_high();
-				self.pins.lines[2].set_high();
-			},
-			8 => {
-				self.pins.lines[3].set_high();
-			},
-			9 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[3].set_high();
-			},
-			10 => {
-				self.pins.lines[1].set_high();
-				self.pins.lines[3].set_high();
-			},
-			11 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[1].set_high();
-				self.pins.lines[3].set_high();
-			},
-			12 => {
-				self.pins.lines[2].set_high();
-				self.pins.lines[3].set_high();
-			},
-			13 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[2].set_high();
-				self.pins.lines[3].set_high();
-			},
-			14 => {
-				self.pins.lines[1].set_high();
-				self.pins.lines[2].set_high();
-				self.pins.lines[3].set_high();
-			},
-			15 => {
-				self.pins.lines[0].set_high();
-				self.pins.lines[1].set_high();
-				self.pins.lines[2].set_high();
-				self.pins.lines[3].set_high();
+	/// Set how images are placed on the panel as mounted. Drawing code keeps
+	/// using logical coordinates; `frame_format` reports the image size.
+	pub fn set_transform(&mut self, transform: DisplayTransform) {
+		self.format.set_transform(transform);
+	}
+
+	/// Convert an image into the bit planes for this panel's layout, scan and
+	/// color depth.
+	pub fn convert(&self, img_buffer: &ImgBuffer) -> Frame {
+		self.format.convert(img_buffer)
+	}
+
+	/// A copy of the current conversion settings, for converting images on
+	/// another thread.
+	pub fn frame_format(&self) -> FrameFormat {
+		self.format.clone()
+	}
+
+	/// True when the next `strobe_row` starts a new pass over the panel, the
+	/// only point where a new frame can be swapped in without tearing.
+	pub fn at_frame_start(&self) -> bool {
+		self.active_row == 0
+	}
+
+	/// Set how long the least significant bit plane is displayed. Each more
+	/// significant plane is displayed for twice as long as the one before.
+	pub fn set_lsb_time(&mut self, lsb_time: Duration) {
+		self.lsb_time = lsb_time;
+	}
+
+	pub fn set_dead_time(&mut self, dead_time: DeadTime) {
+		self.dead_time = dead_time;
+	}
+
+	/// Time taken to shift one bit plane of a row into the panel, the
+	/// longest of a few tries. Nothing is latched, so what the panel shows
+	/// doesn't change.
+	pub fn measure_shift_time(&mut self, frame: &Frame) -> Duration {
+		(0..8).map(|_| {
+			let start = Instant::now();
+			for &byte in frame.row(0, 0) {
+				self.pins.shift_byte(byte);
 			}
-			_ => {}
-		}
+			start.elapsed()
+		}).max().unwrap_or_default()
+	}
+
+	/// How long `strobe_row` takes when each bit plane takes `shift_time` to
+	/// shift in: the planes' display times plus shifting and dead time for
+	/// every plane.
+	pub fn row_period(&self, shift_time: Duration) -> Duration {
+		let depth = self.format.depth() as u32;
+		let dead = self.dead_time.blank_ns + self.dead_time.settle_ns + self.dead_time.precharge_ns;
+
+		self.lsb_time * ((1 << depth) - 1) + (shift_time + Duration::from_nanos(dead)) * depth
 	}
 
-	pub fn clock(&mut self) {
-		self.pins.clk.set_high();
-		sleep(Duration::new(0, 1));
-		self.pins.clk.set_low();
+	/// Handle to the panel brightness, which sets how much of each bit
+	/// plane's display time OE is enabled for. It can be changed from other
+	/// threads while the panel is strobing.
+	pub fn brightness(&self) -> Brightness {
+		self.brightness.clone()
 	}
 
-	pub fn latch(&mut self) {
-		self.pins.lat.set_high();
-		sleep(Duration::new(0, 1));
-		self.pins.lat.set_low();
+	pub fn pins(&self) -> &P {
+		&self.pins
+	}
+
+	/// Display the active row using Binary Code Modulation: every bit plane
+	/// of the row is shifted in and shown for a time weighted by its
+	/// significance. Brightness scales how much of that time the row is lit,
+	/// so dimming doesn't change the refresh rate.
+	pub fn strobe_row(&mut self, frame: &Frame) {
+		let brightness = self.brightness.get() as u32;
+
+		for plane in 0..frame.depth() {
+			for &byte in frame.row(self.active_row, plane) {
+				self.pins.shift_byte(byte);
+			}
+
+			self.pins.output_enable(false);
+			pause(self.dead_time.blank_ns);
+
+			if self.dead_time.precharge_ns > 0 {
+				self.pins.select_row(self.active_row);
+				pause(self.dead_time.precharge_ns);
+				self.pins.latch();
+			} else {
+				self.pins.latch();
+				self.pins.select_row(self.active_row);
+			}
+
+			pause(self.dead_time.settle_ns);
+
+			let slot = self.lsb_time * (1 << plane);
+			let lit = slot * brightness / 100;
+
+			// Disabling OE right after the lit time also keeps the time spent
+			// shifting the next plane from adding to this one
+			if brightness > 0 {
+				self.pins.output_enable(true);
+				sleep(lit);
+				self.pins.output_enable(false);
+			}
+
+			sleep(slot - lit);
+		}
+
+		self.active_row = (self.active_row + 1) % self.scan.row_count();
 	}
 
 	pub fn blank(&mut self) {
-		for line in self.pins.lines.iter_mut() {
-			line.set_low();
+		self.pins.blank();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::img_buffer::{Color, Rgb};
+	use crate::pins::{Pin, PinEvent, RecordingPins};
+
+	fn high(pin: Pin) -> PinEvent {
+		PinEvent { pin, high: true }
+	}
+
+	fn low(pin: Pin) -> PinEvent {
+		PinEvent { pin, high: false }
+	}
+
+	fn test_panel(layout: PanelLayout, scan: ScanRate, lines: usize) -> Hub75Panel<RecordingPins> {
+		let mut panel = Hub75Panel::new(layout, scan, DriverChip::Shift, RecordingPins::new(lines));
+		panel.set_depth(1);
+		panel.set_color_correction(ColorCorrection::LINEAR);
+		panel.set_lsb_time(Duration::ZERO);
+		panel
+	}
+
+	fn line_events(panel: &Hub75Panel<RecordingPins>) -> Vec<PinEvent> {
+		panel.pins().events().iter()
+			.filter(|e| matches!(e.pin, Pin::Line(_)))
+			.cloned()
+			.collect()
+	}
+
+	#[test]
+	fn strobe_row_shifts_then_latches() {
+		let mut image = ImgBuffer::new(2, 32);
+		image.set_pixel(1, 0, Color::Red);
+		image.set_pixel(0, 16, Color::Blue);
+
+		let mut panel = test_panel(PanelLayout::single(2, 32), ScanRate::Sixteenth, 4);
+		let frame = panel.convert(&image);
+		panel.strobe_row(&frame);
+
+		assert_eq!(panel.pins().events(), &[
+			high(Pin::B(1)),
+			high(Pin::Clk),
+			low(Pin::Clk),
+			high(Pin::R(0)),
+			low(Pin::B(1)),
+			high(Pin::Clk),
+			low(Pin::Clk),
+			high(Pin::Oe),
+			high(Pin::Lat),
+			low(Pin::Lat),
+			low(Pin::Oe),
+			high(Pin::Oe),
+		]);
+	}
+
+	#[test]
+	fn each_bit_plane_is_latched() {
+		let mut image = ImgBuffer::new(1, 32);
+		image.set_pixel(0, 17, Rgb::new(0b1010_0000, 0, 0));
+
+		let mut panel = test_panel(PanelLayout::single(1, 32), ScanRate::Sixteenth, 4);
+		panel.set_depth(3);
+		let frame = panel.convert(&image);
+		panel.strobe_row(&frame);
+		panel.strobe_row(&frame);
+
+		let events = panel.pins().events();
+		let latches = events.iter().filter(|e| **e == high(Pin::Lat)).count();
+		assert_eq!(latches, 6);
+
+		// Row 1 shows the lower half pixel on planes 0 and 2 only
+		let r1: Vec<bool> = events.iter()
+			.skip_while(|e| **e != high(Pin::Line(0)))
+			.filter(|e| e.pin == Pin::R(1))
+			.map(|e| e.high)
+			.collect();
+		assert_eq!(r1, vec![false, true]);
+	}
+
+	#[test]
+	fn scan_rate_sets_address_lines() {
+		assert_eq!(ScanRate::Quarter.address_lines(), 2);
+		assert_eq!(ScanRate::Eighth.address_lines(), 3);
+		assert_eq!(ScanRate::Sixteenth.address_lines(), 4);
+		assert_eq!(ScanRate::ThirtySecond.address_lines(), 5);
+	}
+
+	#[test]
+	fn thirty_second_scan_uses_line_e() {
+		let mut panel = test_panel(PanelLayout::single(1, 64), ScanRate::ThirtySecond, 5);
+		let frame = panel.convert(&ImgBuffer::new(1, 64));
+
+		for _ in 0..17 {
+			panel.strobe_row(&frame);
+		}
+
+		assert_eq!(line_events(&panel).last(), Some(&high(Pin::Line(4))));
+	}
+
+	#[test]
+	fn quarter_scan_wraps_after_four_rows() {
+		let mut panel = test_panel(PanelLayout::single(1, 8), ScanRate::Quarter, 2);
+		let frame = panel.convert(&ImgBuffer::new(1, 8));
+
+		for _ in 0..5 {
+			panel.strobe_row(&frame);
 		}
 
-		self.pins.oe.set_low();
+		assert_eq!(line_events(&panel), vec![
+			high(Pin::Line(0)),
+			low(Pin::Line(0)),
+			high(Pin::Line(1)),
+			high(Pin::Line(0)),
+			low(Pin::Line(0)),
+			low(Pin::Line(1)),
+		]);
+	}
+
+	#[test]
+	#[should_panic]
+	fn too_few_address_lines() {
+		test_panel(PanelLayout::single(1, 64), ScanRate::ThirtySecond, 4);
+	}
+
+	#[test]
+	fn chained_panels_shift_like_one_wide_panel() {
+		let mut image = ImgBuffer::new(4, 8);
+		image.set_pixel(2, 0, Color::Green);
+
+		let mut panel = test_panel(PanelLayout::chain(2, 8, 2), ScanRate::Quarter, 2);
+		let frame = panel.convert(&image);
+		panel.strobe_row(&frame);
+
+		// The first pixel of the second module is shifted third of four
+		// columns, as it would be on a single 4 pixel wide module
+		let green: Vec<PinEvent> = panel.pins().events().iter()
+			.filter(|e| matches!(e.pin, Pin::G(0) | Pin::Clk))
+			.cloned()
+			.collect();
+		assert_eq!(&green[..7], &[
+			high(Pin::Clk),
+			low(Pin::Clk),
+			high(Pin::Clk),
+			low(Pin::Clk),
+			high(Pin::G(0)),
+			high(Pin::Clk),
+			low(Pin::Clk),
+		]);
+	}
+
+	#[test]
+	fn row_period_covers_every_plane() {
+		let mut panel = test_panel(PanelLayout::single(1, 8), ScanRate::Quarter, 2);
+		panel.set_depth(4);
+		panel.set_lsb_time(Duration::from_micros(10));
+		panel.set_dead_time(DeadTime { blank_ns: 500, settle_ns: 500, ..DeadTime::default() });
+
+		// 15 LSB times of display, then 4 planes of shifting and dead time
+		assert_eq!(panel.row_period(Duration::from_micros(2)), Duration::from_micros(150 + 4 * 3));
+	}
+
+	#[test]
+	fn zero_brightness_keeps_output_disabled() {
+		let mut panel = test_panel(PanelLayout::single(1, 8), ScanRate::Quarter, 2);
+		let frame = panel.convert(&ImgBuffer::new(1, 8));
+
+		let brightness = panel.brightness();
+		brightness.set(0);
+		panel.strobe_row(&frame);
+		assert!(!panel.pins().events().contains(&low(Pin::Oe)));
+
+		brightness.set(50);
+		panel.strobe_row(&frame);
+		assert!(panel.pins().events().contains(&low(Pin::Oe)));
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn precharge_selects_row_before_latching() {
+		let mut panel = test_panel(PanelLayout::single(1, 8), ScanRate::Quarter, 2);
+		panel.set_dead_time(DeadTime { precharge_ns: 1, ..DeadTime::default() });
+		let frame = panel.convert(&ImgBuffer::new(1, 8));
+
+		panel.strobe_row(&frame);
+		panel.strobe_row(&frame);
+
+		let events: Vec<PinEvent> = panel.pins().events().iter()
+			.filter(|e| matches!(e.pin, Pin::Lat | Pin::Line(_)))
+			.cloned()
+			.collect();
+		assert_eq!(events, vec![
+			high(Pin::Lat),
+			low(Pin::Lat),
+			high(Pin::Line(0)),
+			high(Pin::Lat),
+			low(Pin::Lat),
+		]);
+	}
+}
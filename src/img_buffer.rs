@@ -1,9 +1,57 @@
+use std::convert::Infallible;
+use embedded_graphics::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics::prelude::{DrawTarget, OriginDimensions, Pixel, Size};
 use crate::font::{Font, FontChar};
 
+/// A drawing surface of RGB888 pixels in plain x/y order. `Hub75Panel`
+/// converts it into the bit planes the panel needs.
+///
+/// Every drawing call is clipped to the buffer and to the clip rectangle on
+/// top of the clip stack, so drawing may start or run off anywhere.
 pub struct ImgBuffer {
-    plane: Vec<u8>,
+    pixels: Vec<Rgb>,
     rows: usize,
     cols: usize,
+    /// Clip rectangles, each already inside the one below it.
+    clips: Vec<Rect>,
+}
+
+/// A rectangle of pixels. The position may be negative or off the buffer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rect {
+    pub x: isize,
+    pub y: isize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: isize, y: isize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// The column just right of the rectangle.
+    pub fn right(&self) -> isize {
+        self.x + self.width as isize
+    }
+
+    /// The row just below the rectangle.
+    pub fn bottom(&self) -> isize {
+        self.y + self.height as isize
+    }
+
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        (self.x..self.right()).contains(&x) && (self.y..self.bottom()).contains(&y)
+    }
+
+    /// The area covered by both rectangles, empty if they don't overlap.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right()).max(x);
+        let bottom = self.bottom().min(other.bottom()).max(y);
+        Rect::new(x, y, (right - x) as usize, (bottom - y) as usize)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -17,64 +65,300 @@ pub enum Color {
     White = 0x07,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl From<Color> for Rgb {
+    fn from(color: Color) -> Self {
+        let bits = color as u8;
+        let level = |mask: u8| if bits & mask != 0 { 255 } else { 0 };
+        Rgb::new(level(1), level(2), level(4))
+    }
+}
+
+impl From<Rgb888> for Rgb {
+    fn from(color: Rgb888) -> Self {
+        Rgb::new(color.r(), color.g(), color.b())
+    }
+}
+
+impl From<Rgb> for Rgb888 {
+    fn from(color: Rgb) -> Self {
+        Rgb888::new(color.r, color.g, color.b)
+    }
+}
+
 impl ImgBuffer {
     pub fn new(cols: usize, rows: usize) -> ImgBuffer {
         ImgBuffer {
-            plane: vec![0; cols*rows],
+            pixels: vec![Rgb::default(); cols*rows],
             rows,
             cols,
+            clips: vec![],
         }
     }
 
-    pub fn get_display_row(&self, row: usize) -> &[u8] {
-        let len = self.cols;
-        let start = row * len;
-        let finish = row * len + len;
-        &self.plane[start..finish]
+    /// The area drawing calls currently reach.
+    pub fn clip(&self) -> Rect {
+        self.clips.last().copied().unwrap_or(Rect::new(0, 0, self.cols, self.rows))
+    }
+
+    /// Restrict drawing to `rect` within the current clip area until the
+    /// matching `pop_clip`.
+    pub fn push_clip(&mut self, rect: Rect) {
+        let clip = self.clip().intersect(&rect);
+        self.clips.push(clip);
     }
 
-    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
-        let i = (y % (self.rows / 2)) * self.cols + x;
+    /// Go back to the clip area before the last `push_clip`.
+    pub fn pop_clip(&mut self) -> Option<Rect> {
+        self.clips.pop()
+    }
+
+    pub fn width(&self) -> usize {
+        self.cols
+    }
 
-        let mut data = self.plane[i];
+    pub fn height(&self) -> usize {
+        self.rows
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.cols + x]
+    }
 
-        if y < self.rows / 2 {
-            data &= 0b11111000;
-            data |= color as u8;
-        } else {
-            data &= 0b1100011;
-            data |= (color as u8) << 3;
+    pub fn set_pixel<C: Into<Rgb>>(&mut self, x: usize, y: usize, color: C) {
+        if let (Ok(x), Ok(y)) = (isize::try_from(x), isize::try_from(y)) {
+            self.plot(x, y, color);
         }
-        
-        self.plane[i] = data;
     }
 
-    fn draw_font_char(&mut self, font_char: &FontChar, x0: usize, y0: usize, color: Color) {
+    /// Set a pixel at a signed position, skipping it when it is clipped.
+    pub fn plot<C: Into<Rgb>>(&mut self, x: isize, y: isize, color: C) {
+        if self.clip().contains(x, y) {
+            self.pixels[y as usize * self.cols + x as usize] = color.into();
+        }
+    }
+
+    /// Clear the whole buffer, ignoring the clip area.
+    pub fn clear(&mut self) {
+        self.pixels.fill(Rgb::default());
+    }
+
+    fn draw_font_char(&mut self, font_char: &FontChar, x0: isize, y0: isize, color: Rgb) {
         let data = font_char.data();
         let width = font_char.width();
+        let x0 = x0 + font_char.x_offset();
+        let y0 = y0 + font_char.y_offset();
 
         for (i, d) in data.iter().enumerate() {
-            let x = (i % width) + x0;
-            let y = (i / width) + y0;
+            let x = (i % width) as isize + x0;
+            let y = (i / width) as isize + y0;
 
             if *d > 0 {
-                self.set_pixel(x, y, color);
+                self.plot(x, y, color);
             }
         }
     }
 
-    pub fn draw_str(&mut self, text: &str, font: &Font, x0: usize, y0: usize, color: Color) {
+    pub fn draw_str<C: Into<Rgb>>(&mut self, text: &str, font: &Font, x0: isize, y0: isize, color: C) {
+        let color = color.into();
         let mut x = x0;
-        let char_spacing = 1;
 
         for c in text.chars() {
             match font.char(&c) {
                 Some(font_char) => {
                     self.draw_font_char(font_char, x, y0, color);
-                    x += font_char.width() + char_spacing;
+                    x += (font_char.advance() + font.char_spacing()) as isize;
                 },
                 None => {}
             }
         }
     }
 }
+
+/// Where text goes across the box it is drawn in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl ImgBuffer {
+    /// Draw `text` at the top of `rect`, aligned across it and clipped to it.
+    pub fn draw_str_aligned<C: Into<Rgb>>(&mut self, text: &str, font: &Font, rect: Rect, align: Align, color: C) {
+        let spare = rect.width as isize - font.text_width(text) as isize;
+        let x = match align {
+            Align::Left => rect.x,
+            Align::Center => rect.x + spare / 2,
+            Align::Right => rect.x + spare,
+        };
+
+        self.push_clip(rect);
+        self.draw_str(text, font, x, rect.y, color);
+        self.pop_clip();
+    }
+}
+
+impl OriginDimensions for ImgBuffer {
+    fn size(&self) -> Size {
+        Size::new(self.cols as u32, self.rows as u32)
+    }
+}
+
+/// Lets embedded-graphics primitives, images and text draw onto the sign.
+/// Pixels outside the clip area are skipped.
+impl DrawTarget for ImgBuffer {
+    type Color = Rgb888;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            self.plot(point.x as isize, point.y as isize, color);
+        }
+
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.pixels.fill(color.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_are_stored_in_xy_order() {
+        let mut image = ImgBuffer::new(3, 2);
+        image.set_pixel(2, 1, Color::Yellow);
+        image.set_pixel(0, 1, Rgb::new(1, 2, 3));
+
+        assert_eq!(image.pixel(2, 1), Rgb::new(255, 255, 0));
+        assert_eq!(image.pixel(0, 1), Rgb::new(1, 2, 3));
+        assert_eq!(image.pixel(2, 0), Rgb::default());
+
+        image.clear();
+        assert_eq!(image.pixel(2, 1), Rgb::default());
+    }
+
+    #[test]
+    fn draws_embedded_graphics_primitives() {
+        use embedded_graphics::prelude::*;
+        use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+
+        let mut image = ImgBuffer::new(8, 4);
+        assert_eq!(image.size(), Size::new(8, 4));
+
+        Rectangle::new(Point::new(6, 2), Size::new(4, 4))
+            .into_styled(PrimitiveStyle::with_fill(Rgb888::RED))
+            .draw(&mut image)
+            .unwrap();
+
+        assert_eq!(image.pixel(5, 2), Rgb::default());
+        assert_eq!(image.pixel(6, 2), Rgb::new(255, 0, 0));
+        assert_eq!(image.pixel(7, 3), Rgb::new(255, 0, 0));
+
+        DrawTarget::clear(&mut image, Rgb888::BLUE).unwrap();
+        assert_eq!(image.pixel(0, 0), Rgb::new(0, 0, 255));
+    }
+
+    #[test]
+    fn draws_embedded_graphics_text() {
+        use embedded_graphics::mono_font::{ascii::FONT_4X6, MonoTextStyle};
+        use embedded_graphics::prelude::*;
+        use embedded_graphics::text::{Baseline, Text};
+
+        let mut image = ImgBuffer::new(8, 6);
+        let style = MonoTextStyle::new(&FONT_4X6, Rgb888::WHITE);
+        Text::with_baseline("II", Point::new(-2, 0), style, Baseline::Top)
+            .draw(&mut image)
+            .unwrap();
+
+        let lit = (0..8).flat_map(|x| (0..6).map(move |y| (x, y)))
+            .filter(|&(x, y)| image.pixel(x, y) != Rgb::default())
+            .count();
+        assert!(lit > 0);
+    }
+
+    #[test]
+    fn pixels_off_the_buffer_are_skipped() {
+        let mut image = ImgBuffer::new(3, 2);
+        image.set_pixel(3, 0, Color::Red);
+        image.plot(-1, 1, Color::Red);
+        image.plot(0, 2, Color::Red);
+
+        assert!(image.pixels.iter().all(|&p| p == Rgb::default()));
+    }
+
+    #[test]
+    fn clip_rectangles_nest() {
+        let mut image = ImgBuffer::new(8, 4);
+        image.push_clip(Rect::new(2, 0, 4, 4));
+        image.push_clip(Rect::new(-5, 1, 8, 10));
+        assert_eq!(image.clip(), Rect::new(2, 1, 1, 3));
+
+        image.plot(3, 1, Color::Red);
+        assert_eq!(image.pixel(3, 1), Rgb::default());
+
+        image.pop_clip();
+        image.plot(3, 1, Color::Red);
+        assert_eq!(image.pixel(3, 1), Rgb::new(255, 0, 0));
+
+        image.pop_clip();
+        assert_eq!(image.clip(), Rect::new(0, 0, 8, 4));
+        assert_eq!(image.pop_clip(), None);
+    }
+
+    #[test]
+    fn disjoint_clip_draws_nothing() {
+        let mut image = ImgBuffer::new(4, 4);
+        image.push_clip(Rect::new(10, 10, 2, 2));
+        assert_eq!(image.clip().width * image.clip().height, 0);
+
+        image.set_pixel(0, 0, Color::White);
+        assert_eq!(image.pixel(0, 0), Rgb::default());
+    }
+
+    #[test]
+    fn text_is_aligned_in_a_box() {
+        let font = Font::load("fonts/metronorth.toml").unwrap();
+        let lit_columns = |image: &ImgBuffer| -> Vec<usize> {
+            (0..image.width()).filter(|&x| image.pixel(x, 0) != Rgb::default()).collect()
+        };
+
+        let mut image = ImgBuffer::new(10, 7);
+        image.draw_str_aligned("II", &font, Rect::new(1, 0, 8, 7), Align::Left, Color::Red);
+        assert_eq!(lit_columns(&image), vec![1, 3]);
+
+        image.clear();
+        image.draw_str_aligned("II", &font, Rect::new(1, 0, 8, 7), Align::Center, Color::Red);
+        assert_eq!(lit_columns(&image), vec![3, 5]);
+
+        image.clear();
+        image.draw_str_aligned("II", &font, Rect::new(1, 0, 8, 7), Align::Right, Color::Red);
+        assert_eq!(lit_columns(&image), vec![6, 8]);
+
+        // Text wider than the box is cut off at the box
+        image.clear();
+        image.draw_str_aligned("IIIII", &font, Rect::new(1, 0, 4, 7), Align::Right, Color::Red);
+        assert_eq!(lit_columns(&image), vec![2, 4]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::sync::Arc;
+use serde::Deserialize;
+use crate::mapping::{self, Direct, PixelMapping};
+
+/// Clockwise rotation of a panel relative to the logical image. In config
+/// files it is written in degrees.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(try_from = "u32")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    fn quarter_turns(self) -> usize {
+        match self {
+            Rotation::None => 0,
+            Rotation::Cw90 => 1,
+            Rotation::Cw180 => 2,
+            Rotation::Cw270 => 3,
+        }
+    }
+
+    fn from_quarter_turns(turns: usize) -> Self {
+        match turns % 4 {
+            0 => Rotation::None,
+            1 => Rotation::Cw90,
+            2 => Rotation::Cw180,
+            _ => Rotation::Cw270,
+        }
+    }
+
+    /// The rotation that undoes this one.
+    pub fn inverse(self) -> Rotation {
+        Rotation::from_quarter_turns(4 - self.quarter_turns())
+    }
+
+    pub fn then(self, other: Rotation) -> Rotation {
+        Rotation::from_quarter_turns(self.quarter_turns() + other.quarter_turns())
+    }
+
+    /// Size of a `width` x `height` area after rotating it by this amount.
+    pub fn size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+            Rotation::None | Rotation::Cw180 => (width, height),
+        }
+    }
+
+    /// Position of pixel (x, y) of a `width` x `height` area after rotating
+    /// the area by this amount.
+    pub fn apply(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (height - 1 - y, x),
+            Rotation::Cw180 => (width - 1 - x, height - 1 - y),
+            Rotation::Cw270 => (y, width - 1 - x),
+        }
+    }
+}
+
+impl TryFrom<u32> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u32) -> Result<Self, Self::Error> {
+        match degrees {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Cw90),
+            180 => Ok(Rotation::Cw180),
+            270 => Ok(Rotation::Cw270),
+            _ => Err(format!("Rotation of {} degrees isn't a multiple of 90", degrees)),
+        }
+    }
+}
+
+/// How the modules behind one HUB75 connector are arranged to form the sign.
+///
+/// Modules are numbered in the order their columns are shifted in, which is
+/// the same order as columns within a module, so a chain of modules shows
+/// exactly what one module as wide as the chain would. Module 0 is the one
+/// at the far end of the chain from the connector. Modules fill the sign
+/// left to right, top to bottom in `tile_rows` rows. With `serpentine` set,
+/// every other row runs right to left with the modules mounted upside down,
+/// as in a U-turn cable run.
+#[derive(Clone)]
+pub struct PanelLayout {
+    pub panel_cols: usize,
+    pub panel_rows: usize,
+    pub chain: usize,
+    pub tile_rows: usize,
+    /// Rotation of each module in chain order. Missing entries are unrotated.
+    pub rotations: Vec<Rotation>,
+    pub serpentine: bool,
+    /// How each module's pixels are wired to its shift registers.
+    pub mapping: Arc<dyn PixelMapping + Send + Sync>,
+}
+
+impl PanelLayout {
+    pub fn single(cols: usize, rows: usize) -> Self {
+        Self::chain(cols, rows, 1)
+    }
+
+    pub fn chain(panel_cols: usize, panel_rows: usize, chain: usize) -> Self {
+        Self {
+            panel_cols,
+            panel_rows,
+            chain,
+            tile_rows: 1,
+            rotations: vec![],
+            serpentine: false,
+            mapping: Arc::new(Direct),
+        }
+    }
+
+    pub fn tiles_per_row(&self) -> usize {
+        self.chain / self.tile_rows
+    }
+
+    /// Width of the whole sign in pixels.
+    pub fn width(&self) -> usize {
+        self.tiles_per_row() * self.panel_cols
+    }
+
+    /// Height of the whole sign in pixels.
+    pub fn height(&self) -> usize {
+        self.tile_rows * self.panel_rows
+    }
+
+    /// Number of columns shifted in for each row address.
+    pub fn shift_len(&self) -> usize {
+        self.chain * self.panel_cols * self.mapping.factor()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chain == 0 || self.tile_rows == 0 || !self.chain.is_multiple_of(self.tile_rows) {
+            return Err(format!("Chain of {} can't be split into {} tile rows",
+                               self.chain, self.tile_rows));
+        }
+
+        let turned = self.rotations.iter()
+            .any(|r| matches!(r, Rotation::Cw90 | Rotation::Cw270));
+
+        if turned && self.panel_cols != self.panel_rows {
+            return Err("Only square panels can be rotated by 90 or 270 degrees".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Logical position of pixel (x, y) of the module at `chain_pos`.
+    pub fn locate(&self, chain_pos: usize, x: usize, y: usize) -> (usize, usize) {
+        let per_row = self.tiles_per_row();
+        let tile_row = chain_pos / per_row;
+        let mut tile_col = chain_pos % per_row;
+        let mut rotation = self.rotations.get(chain_pos).copied().unwrap_or(Rotation::None);
+
+        if self.serpentine && tile_row % 2 == 1 {
+            tile_col = per_row - 1 - tile_col;
+            rotation = rotation.then(Rotation::Cw180);
+        }
+
+        let (tx, ty) = rotation.apply(x, y, self.panel_cols, self.panel_rows);
+        (tile_col * self.panel_cols + tx, tile_row * self.panel_rows + ty)
+    }
+
+    /// Logical pixels for the upper and lower half of every shifted column,
+    /// indexed by row address then shift position. The first columns shifted
+    /// in travel to the far end of the chain, module 0.
+    pub fn shift_map(&self, scan_rows: usize) -> Vec<[(usize, usize); 2]> {
+        let width = self.panel_cols * self.mapping.factor();
+        let table = mapping::shift_table(&*self.mapping, self.panel_cols, self.panel_rows);
+
+        let locate = |column: usize, row: usize| {
+            let chain_pos = column / width;
+            let (x, y) = table[row * width + column % width];
+            self.locate(chain_pos, x, y)
+        };
+        let locate = &locate;
+
+        (0..scan_rows).flat_map(|row| {
+            (0..self.shift_len()).map(move |column| [
+                locate(column, row),
+                locate(column, row + scan_rows),
+            ])
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_panel_is_identity() {
+        let layout = PanelLayout::single(64, 32);
+        let map = layout.shift_map(16);
+        assert_eq!(layout.width(), 64);
+        assert_eq!(map[0], [(0, 0), (0, 16)]);
+        assert_eq!(map[15 * 64 + 63], [(63, 15), (63, 31)]);
+    }
+
+    #[test]
+    fn chain_fills_left_to_right() {
+        let layout = PanelLayout::chain(64, 32, 2);
+        assert_eq!(layout.width(), 128);
+        assert_eq!(layout.height(), 32);
+
+        // The first 64 columns shifted end up on the far module, module 0
+        let map = layout.shift_map(16);
+        assert_eq!(map[5 * 128], [(0, 5), (0, 21)]);
+        assert_eq!(map[5 * 128 + 64], [(64, 5), (64, 21)]);
+    }
+
+    #[test]
+    fn chain_matches_one_wide_panel() {
+        let chained = PanelLayout::chain(64, 32, 2).shift_map(16);
+        let single = PanelLayout::single(128, 32).shift_map(16);
+        assert_eq!(chained, single);
+    }
+
+    #[test]
+    fn serpentine_flips_every_other_row() {
+        let layout = PanelLayout {
+            tile_rows: 2,
+            serpentine: true,
+            ..PanelLayout::chain(64, 32, 4)
+        };
+        layout.validate().unwrap();
+        assert_eq!(layout.width(), 128);
+        assert_eq!(layout.height(), 64);
+
+        assert_eq!(layout.locate(0, 0, 0), (0, 0));
+        assert_eq!(layout.locate(1, 0, 0), (64, 0));
+        assert_eq!(layout.locate(2, 0, 0), (127, 63));
+        assert_eq!(layout.locate(3, 0, 0), (63, 63));
+        assert_eq!(layout.locate(3, 63, 31), (0, 32));
+    }
+
+    #[test]
+    fn tile_rotation() {
+        let layout = PanelLayout {
+            rotations: vec![Rotation::None, Rotation::Cw90],
+            ..PanelLayout::chain(32, 32, 2)
+        };
+        layout.validate().unwrap();
+
+        assert_eq!(layout.locate(1, 0, 0), (63, 0));
+        assert_eq!(layout.locate(1, 0, 31), (32, 0));
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let uneven = PanelLayout { tile_rows: 2, ..PanelLayout::chain(64, 32, 3) };
+        assert!(uneven.validate().is_err());
+
+        let turned = PanelLayout { rotations: vec![Rotation::Cw90], ..PanelLayout::single(64, 32) };
+        assert!(turned.validate().is_err());
+    }
+
+    #[test]
+    fn outdoor_mapping_doubles_shift_length() {
+        let layout = PanelLayout {
+            mapping: Arc::new(mapping::ZStripe { block: 8, upper_first: true }),
+            ..PanelLayout::chain(32, 16, 2)
+        };
+        let map = layout.shift_map(4);
+
+        assert_eq!(layout.shift_len(), 128);
+        assert_eq!(map.len(), 4 * 128);
+        assert_eq!(map[0], [(0, 0), (0, 8)]);
+        assert_eq!(map[8], [(0, 4), (0, 12)]);
+        assert_eq!(map[64 + 16], [(40, 0), (40, 8)]);
+    }
+}
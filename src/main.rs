@@ -1,43 +1,163 @@
 use std::error::Error;
+use std::io;
 use std::thread;
+use std::thread::JoinHandle;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
 use std::env;
 
+use chrono::{Local, Timelike};
 use dotenv::dotenv;
 use rppal::system::DeviceInfo;
 use spin_sleep::LoopHelper;
 
 mod hub75;
+mod brightness;
+mod config;
+mod color_correction;
+mod frame;
+mod frame_swap;
+mod driver_chip;
+mod layout;
+mod mapping;
+mod pins;
+mod gpiomem;
+mod realtime;
+mod telemetry;
+mod transform;
 mod img_buffer;
+mod draw;
+mod marquee;
+mod text_block;
+mod sprite;
+mod export;
+mod terminal;
 mod font;
+mod bdf;
 mod ppm;
 mod mta;
 
-use hub75::{Hub75PinNums, Hub75Panel};
-use img_buffer::{ImgBuffer, Color};
+use brightness::Brightness;
+use font::Font;
+use hub75::Hub75Panel;
+use frame_swap::frame_swap;
+use layout::PanelLayout;
+use marquee::Marquee;
+use pins::{Hub75PinNums, Hub75Pins, RppalPins};
+use gpiomem::GpioMemPins;
+use img_buffer::{Align, ImgBuffer, Rect, Rgb};
+use telemetry::{Telemetry, TimingRecorder};
+use terminal::TerminalDisplay;
 
+use crate::config::{Config, Output, PinBackend};
 use crate::mta::MTAStatic;
 
-fn draw_eta_line(image: &mut ImgBuffer, font: &font::Font, y: usize, time: &str, destination: &str, status: &str, track: &str) {
-    image.draw_str(time, font, 0, y, Color::Yellow);
-    image.draw_str(destination, font, 20, y, Color::Yellow);
-    image.draw_str(status, font, 84, y, Color::Yellow);
-    image.draw_str(track, font, 120, y, Color::Yellow);
+const CONFIG_FILE: &str = "sign.toml";
+
+const AMBER: Rgb = Rgb::new(255, 140, 0);
+
+/// Sample departures until the MTA feed is wired in: time, destination,
+/// status and track.
+const DEPARTURES: [(&str, &str, &str, &str); 3] = [
+    ("8:24", "New Haven", "On Time", "3"),
+    ("8:27", "Stamford", "On Time", "4"),
+    ("8:34", "Southeast", "On Time", "3"),
+];
+
+/// Board columns as x position, width and alignment.
+const TIME_COLUMN: (isize, usize, Align) = (0, 18, Align::Right);
+const DESTINATION_COLUMN: (isize, usize, Align) = (20, 63, Align::Left);
+const STATUS_COLUMN: (isize, usize, Align) = (84, 33, Align::Left);
+const TRACK_COLUMN: (isize, usize, Align) = (118, 10, Align::Center);
+
+fn draw_in_column(image: &mut ImgBuffer, font: &Font, column: (isize, usize, Align), y: isize, text: &str) {
+    let (x, width, align) = column;
+    image.draw_str_aligned(text, font, Rect::new(x, y, width, 8), align, AMBER);
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let running = Arc::new(AtomicBool::new(true));
+/// Images drawn per second, often enough for scrolling text to look smooth.
+const FRAME_RATE: f32 = 30.0;
 
-    ctrlc::set_handler({
-        let running = running.clone();
-        move || {
-            println!("Shutting down.");
-            running.store(false, Ordering::SeqCst);
+fn draw_eta_line(image: &mut ImgBuffer, font: &Font, y: isize, time: &str, destination: &Marquee, status: &str, track: &str) {
+    draw_in_column(image, font, TIME_COLUMN, y, time);
+    destination.draw(image, font, AMBER);
+    draw_in_column(image, font, STATUS_COLUMN, y, status);
+    draw_in_column(image, font, TRACK_COLUMN, y, track);
+}
+
+/// The departure board. Destinations too long for their column scroll.
+struct Board {
+    destinations: Vec<Marquee>,
+}
+
+impl Board {
+    fn new(font: &Font) -> Self {
+        let destinations = DEPARTURES.iter().enumerate().map(|(i, departure)| {
+            let (x, width, _) = DESTINATION_COLUMN;
+            let region = Rect::new(x, 8 * (i as isize + 1), width, 8);
+            let mut marquee = Marquee::new(departure.1, font, region, 12.0);
+            marquee.set_pause(Duration::from_secs(2));
+            marquee
+        }).collect();
+
+        Self { destinations }
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        for destination in &mut self.destinations {
+            destination.advance(elapsed);
         }
-    }).expect("Error setting ctrl-c handler!");
+    }
+
+    fn draw(&self, image: &mut ImgBuffer, font: &Font) {
+        draw_in_column(image, font, TIME_COLUMN, 0, "TIME");
+        draw_in_column(image, font, DESTINATION_COLUMN, 0, "DESTINATION");
+        draw_in_column(image, font, STATUS_COLUMN, 0, "STATUS");
+        draw_in_column(image, font, TRACK_COLUMN, 0, "TK");
+
+        for (i, (departure, destination)) in DEPARTURES.iter().zip(&self.destinations).enumerate() {
+            let (time, _, status, track) = departure;
+            draw_eta_line(image, font, 8 * (i as isize + 1), time, destination, status, track);
+        }
+    }
+}
+
+/// Draw the board `FRAME_RATE` times a second and pass each image to `show`,
+/// until `running` is cleared or `show` returns false. Every output shares
+/// this loop so they all show the same thing.
+fn render_board<F: FnMut(&ImgBuffer) -> bool>(font: &Font, width: usize, height: usize, running: &AtomicBool, mut show: F) {
+    let mut image = ImgBuffer::new(width, height);
+    let mut board = Board::new(font);
+    let mut last_frame = Instant::now();
 
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        board.advance(now - last_frame);
+        last_frame = now;
+
+        image.clear();
+        board.draw(&mut image, font);
+
+        if !show(&image) {
+            break;
+        }
+
+        thread::sleep(Duration::from_secs_f32(1.0 / FRAME_RATE));
+    }
+}
+
+/// Whatever is showing the board, as started by `start_panel` or
+/// `start_terminal`.
+struct Display {
+    brightness: Brightness,
+    telemetry: Option<Telemetry>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+/// Drive the HUB75 panels: a render thread draws the board and converts it
+/// into frames, and a strobe thread refreshes the panels with them.
+fn start_panel(config: &Config, layout: PanelLayout, font: Font, running: Arc<AtomicBool>) -> Result<Display, Box<dyn Error>> {
     println!("Device info: {}", DeviceInfo::new()?.model());
 
     let pins = Hub75PinNums {
@@ -50,62 +170,67 @@ fn main() -> Result<(), Box<dyn Error>> {
         lat: 21,
     };
 
-    dotenv().ok();
-    let key = env::var("MTA_API_KEY").expect("MTA_API_KEY must be set!");
-    let mta = mta::MTA::new(&key);
-    let _ = mta.get_rt();
-    let _ = MTAStatic::new().load();
-    
-    let mut panel = Hub75Panel::new(128, 32, pins);
-    let mut image = ImgBuffer::new(128, 32);
-    // image.set_pixel(0, 0, Color::Red);
-    // image.set_pixel(10, 10, Color::Green);
-    // image.set_pixel(20, 20, Color::Blue);
-    // image.set_pixel(30, 30, Color::White);
-    // image.set_pixel(40, 0, Color::Yellow);
-    // image.set_pixel(50, 10, Color::Purple);
-    // image.set_pixel(60, 20, Color::Teal);
-
-    // let font57 = font::Font::load("fonts/57.toml")?;
-    // image.draw_str("ABCDEFGHIJ", &font57, 0, 0, Color::Red);
-    // image.draw_str("KLMNOPQRST", &font57, 0, 8, Color::Green);
-    // image.draw_str("UVWXYZabcd", &font57, 0, 16, Color::Blue);
-    // image.draw_str("efghijklmn", &font57, 0, 24, Color::Yellow);
-
-    let font_mta = font::Font::load("fonts/metronorth.toml")?;
-    // image.draw_str("ABCDEFGHIJKLMNOPQRST", &font_mta, 0, 0, Color::Red);
-    // image.draw_str("UVWXYZabcdefghijklmn", &font_mta, 0, 8, Color::Green);
-    // image.draw_str("opqrstuvwxyz01234567", &font_mta, 0, 16, Color::Blue);
-    // image.draw_str("89.,:-", &font_mta, 0, 24, Color::Yellow);
-    image.draw_str("TIME", &font_mta, 0, 0, Color::Yellow);
-    image.draw_str("DESTINATION", &font_mta, 20, 0, Color::Yellow);
-    image.draw_str("STATUS", &font_mta, 84, 0, Color::Yellow);
-    image.draw_str("TK", &font_mta, 118, 0, Color::Yellow);
-
-    draw_eta_line(&mut image, &font_mta, 8, " 8:24", "New Haven", "On Time", "3");
-    draw_eta_line(&mut image, &font_mta, 16, " 8:27", "Stamford", "On Time", "4");
-    draw_eta_line(&mut image, &font_mta, 24, " 8:34", "Southeast", "On Time", "3");
-
-    let mut loop_helper = LoopHelper::builder()
-        .report_interval_s(1.0)
-        .build_with_target_rate(1000.0);
-
-    let (sender, receiver) = channel();
-    
-    let strobe_handle = thread::spawn({
+    let pins: Box<dyn Hub75Pins + Send> = match config.panel.backend {
+        PinBackend::GpioMem => {
+            let mut pins = GpioMemPins::from_pin_nums(&pins)?;
+            pins.set_slowdown(config.panel.slowdown);
+            Box::new(pins)
+        },
+        PinBackend::Rppal => Box::new(RppalPins::from_pin_nums(&pins)?),
+    };
+    let mut panel = Hub75Panel::new(layout, config.panel.layout.scan, config.panel.layout.driver, pins);
+    panel.set_color_correction(config.color);
+    panel.set_dead_time(config.panel.dead_time()?);
+    panel.set_transform(config.display);
+    let format = panel.frame_format();
+    let (width, height) = (format.width(), format.height());
+    let brightness = panel.brightness();
+    let (frame_sender, mut frames) = frame_swap(format.convert(&ImgBuffer::new(width, height)));
+
+    let render_handle = thread::spawn({
         let running = running.clone();
+        move || render_board(&font, width, height, &running, |image| {
+            let frame = match frame_sender.spare() {
+                Some(mut frame) => {
+                    format.convert_into(image, &mut frame);
+                    frame
+                },
+                None => format.convert(image),
+            };
+
+            // The strobe thread has stopped
+            frame_sender.send(frame).is_ok()
+        })
+    });
+
+    let telemetry = Telemetry::default();
+    let realtime = config.strobe;
+    let strobe_handle = thread::spawn({
+        let telemetry = telemetry.clone();
         move || {
+            realtime.apply();
             panel.blank();
 
+            // Pace rows at the time they take to strobe, so the panel is lit
+            // for as much of every row as the brightness allows
+            let shift_time = panel.measure_shift_time(frames.current());
+            let row_period = panel.row_period(shift_time);
+            let mut loop_helper = LoopHelper::builder()
+                .build_with_target_rate(1.0 / row_period.as_secs_f64());
+            let mut recorder = TimingRecorder::new(telemetry, row_period, Duration::from_secs(1));
+
             while running.load(Ordering::SeqCst) {
                 loop_helper.loop_start();
+                let row_start = Instant::now();
 
-                panel.strobe_row(&image);
-
-                if let Some(rate) = loop_helper.report_rate() {
-                    sender.send(rate).unwrap();
+                let frame_start = panel.at_frame_start();
+                if frame_start {
+                    frames.swap();
                 }
-            
+
+                panel.strobe_row(frames.current());
+                recorder.record_row(row_start, Instant::now(), frame_start);
+
                 loop_helper.loop_sleep();
             }
 
@@ -113,18 +238,121 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    let info_handle = thread::spawn({
+    Ok(Display {
+        brightness,
+        telemetry: Some(telemetry),
+        threads: vec![strobe_handle, render_handle],
+    })
+}
+
+/// Simulate the panels in the terminal, so the board can run on any Linux
+/// box. The image is shown as the mounted sign would show it.
+fn start_terminal(config: &Config, layout: PanelLayout, font: Font, running: Arc<AtomicBool>) -> Result<Display, Box<dyn Error>> {
+    let transform = config.display;
+    let (width, height) = transform.logical_size(layout.width(), layout.height());
+    let mut terminal = TerminalDisplay::new(io::stdout());
+    let brightness = terminal.brightness();
+
+    let render_handle = thread::spawn(move || {
+        render_board(&font, width, height, &running, |image| {
+            match terminal.show(&image.transformed(&transform)) {
+                Ok(()) => true,
+                Err(e) => {
+                    println!("Can't draw to the terminal: {}", e);
+                    false
+                },
+            }
+        })
+    });
+
+    Ok(Display {
+        brightness,
+        telemetry: None,
+        threads: vec![render_handle],
+    })
+}
+
+/// Fetch the MTA feeds. Only the real sign needs them, so the terminal
+/// simulator runs without an API key or network.
+fn load_mta() {
+    dotenv().ok();
+    let key = env::var("MTA_API_KEY").expect("MTA_API_KEY must be set!");
+    let mta = mta::MTA::new(&key);
+    let _ = mta.get_rt();
+    let _ = MTAStatic::new().load();
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let running = Arc::new(AtomicBool::new(true));
+
+    ctrlc::set_handler({
         let running = running.clone();
         move || {
-            while running.load(Ordering::SeqCst) {
-                let rate = receiver.recv().unwrap();
-                println!("Rate: {}", rate);
+            println!("Shutting down.");
+            running.store(false, Ordering::SeqCst);
+        }
+    }).expect("Error setting ctrl-c handler!");
+
+    let config = Config::load(CONFIG_FILE)?;
+
+    let layout = config.panel.layout.layout();
+    let font_mta = Font::load("fonts/metronorth.toml")?;
+
+    let display = match config.panel.output {
+        Output::Panel => {
+            load_mta();
+            start_panel(&config, layout, font_mta, running.clone())?
+        },
+        Output::Terminal => start_terminal(&config, layout, font_mta, running.clone())?,
+    };
+
+    // Follow the brightness schedule, picking up config changes every minute
+    let mut schedule = config.brightness.schedule()?;
+    let mut last_reload = Instant::now();
+    let mut last_report = Instant::now();
+
+    while running.load(Ordering::SeqCst) {
+        if last_reload.elapsed() >= Duration::from_secs(60) {
+            match Config::load(CONFIG_FILE) {
+                Ok(config) => schedule = config.brightness.schedule()?,
+                Err(e) => println!("Keeping old brightness schedule: {}", e),
             }
+
+            last_reload = Instant::now();
         }
-    });
-    
-    strobe_handle.join().expect("Strobe thread panicked!");
-    info_handle.join().expect("Info thread panicked!");
+
+        if last_report.elapsed() >= Duration::from_secs(10) {
+            if let Some(stats) = display.telemetry.as_ref().and_then(|t| t.latest()) {
+                println!("Refresh: {}", stats);
+            }
+
+            last_report = Instant::now();
+        }
+
+        let now = Local::now();
+        display.brightness.set(schedule.level_at(now.hour() * 60 + now.minute()));
+
+        thread::sleep(Duration::from_secs(1));
+    }
+
+    for handle in display.threads {
+        handle.join().expect("Display thread panicked!");
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::assert_golden;
+
+    #[test]
+    fn departure_board_layout() {
+        let font = Font::load("fonts/metronorth.toml").unwrap();
+        let mut image = ImgBuffer::new(128, 32);
+        Board::new(&font).draw(&mut image, &font);
+
+        assert_golden(&image, "board");
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+use serde::Deserialize;
+
+/// Maps the visible pixels of a module onto its shift registers.
+///
+/// Outdoor modules often light more than two rows per address, so the shift
+/// registers behind one address cover several visible rows and the data
+/// zig-zags between them. Such a module is driven as if it were a plain
+/// panel `factor()` times as wide and `factor()` times less tall, and the
+/// mapping says where each visible pixel sits on that plain panel.
+pub trait PixelMapping {
+    /// How many times wider the shift registers are than the visible module.
+    fn factor(&self) -> usize;
+
+    /// Position on the plain `cols * factor` by `rows / factor` panel of
+    /// visible pixel (x, y) of a `cols` by `rows` module.
+    fn shift_position(&self, x: usize, y: usize, cols: usize, rows: usize) -> (usize, usize);
+}
+
+/// Shift registers are wired straight across each row.
+pub struct Direct;
+
+impl PixelMapping for Direct {
+    fn factor(&self) -> usize {
+        1
+    }
+
+    fn shift_position(&self, x: usize, y: usize, _cols: usize, _rows: usize) -> (usize, usize) {
+        (x, y)
+    }
+}
+
+/// Each half of the module is split into an upper and lower stripe; the
+/// upper stripe is shifted after the lower one.
+pub struct Stripe;
+
+impl PixelMapping for Stripe {
+    fn factor(&self) -> usize {
+        2
+    }
+
+    fn shift_position(&self, x: usize, y: usize, cols: usize, rows: usize) -> (usize, usize) {
+        let stripe = rows / 4;
+        let upper = (y % (rows / 2)) < stripe;
+        let mx = if upper { x + cols } else { x };
+        (mx, (y / (rows / 2)) * stripe + y % stripe)
+    }
+}
+
+/// Like `Stripe`, but each stripe is also split into a left and right half
+/// that are shifted in a checkered order.
+pub struct Checkered;
+
+impl PixelMapping for Checkered {
+    fn factor(&self) -> usize {
+        2
+    }
+
+    fn shift_position(&self, x: usize, y: usize, cols: usize, rows: usize) -> (usize, usize) {
+        let stripe = rows / 4;
+        let upper = (y % (rows / 2)) < stripe;
+        let left = x < cols / 2;
+
+        let mx = match (upper, left) {
+            (true, true) => x + cols / 2,
+            (true, false) => x + cols,
+            (false, true) => x,
+            (false, false) => x + cols / 2,
+        };
+        (mx, (y / (rows / 2)) * stripe + y % stripe)
+    }
+}
+
+/// Data alternates between two stripes every `block` pixels, the common
+/// wiring of cheap 1/4 scan P10 modules. With `upper_first` the block of the
+/// upper stripe is shifted before the block of the stripe below it.
+pub struct ZStripe {
+    pub block: usize,
+    pub upper_first: bool,
+}
+
+impl PixelMapping for ZStripe {
+    fn factor(&self) -> usize {
+        2
+    }
+
+    fn shift_position(&self, x: usize, y: usize, _cols: usize, rows: usize) -> (usize, usize) {
+        let stripe = rows / 4;
+        let lower = (y / stripe) % 2 == 1;
+        let second = lower == self.upper_first;
+
+        let mx = (x / self.block) * 2 * self.block
+            + x % self.block
+            + if second { self.block } else { 0 };
+        (mx, y % stripe + stripe * (y / (2 * stripe)))
+    }
+}
+
+/// The mappings by name, for picking one in the config file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mapping {
+    #[default]
+    Direct,
+    Stripe,
+    Checkered,
+    ZStripe { block: usize, upper_first: bool },
+}
+
+impl Mapping {
+    pub fn build(self) -> Arc<dyn PixelMapping + Send + Sync> {
+        match self {
+            Mapping::Direct => Arc::new(Direct),
+            Mapping::Stripe => Arc::new(Stripe),
+            Mapping::Checkered => Arc::new(Checkered),
+            Mapping::ZStripe { block, upper_first } => Arc::new(ZStripe { block, upper_first }),
+        }
+    }
+}
+
+/// Visible pixel of a `cols` by `rows` module for every position of the
+/// plain panel the mapping drives, indexed by row then column.
+pub fn shift_table(mapping: &dyn PixelMapping, cols: usize, rows: usize) -> Vec<(usize, usize)> {
+    let factor = mapping.factor();
+    let width = cols * factor;
+    let mut table = vec![(0, 0); cols * rows];
+
+    for y in 0..rows {
+        for x in 0..cols {
+            let (mx, my) = mapping.shift_position(x, y, cols, rows);
+            assert!(mx < width && my < rows / factor, "Pixel mapping is out of range");
+            table[my * width + mx] = (x, y);
+        }
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_permutation(mapping: &dyn PixelMapping, cols: usize, rows: usize) -> bool {
+        let mut table = shift_table(mapping, cols, rows);
+        table.sort();
+        table.dedup();
+        table.len() == cols * rows
+    }
+
+    #[test]
+    fn mappings_cover_every_pixel() {
+        assert!(is_permutation(&Direct, 32, 16));
+        assert!(is_permutation(&Stripe, 32, 16));
+        assert!(is_permutation(&Checkered, 32, 16));
+        assert!(is_permutation(&ZStripe { block: 8, upper_first: true }, 32, 16));
+        assert!(is_permutation(&ZStripe { block: 8, upper_first: false }, 32, 16));
+        assert!(is_permutation(&ZStripe { block: 16, upper_first: true }, 32, 16));
+    }
+
+    #[test]
+    fn zstripe_alternates_blocks() {
+        let table = shift_table(&ZStripe { block: 8, upper_first: true }, 32, 16);
+
+        // First row of the plain 64x8 panel
+        assert_eq!(table[0], (0, 0));
+        assert_eq!(table[7], (7, 0));
+        assert_eq!(table[8], (0, 4));
+        assert_eq!(table[16], (8, 0));
+        assert_eq!(table[63], (31, 4));
+
+        // The lower half of the module starts at plain row 4
+        assert_eq!(table[4 * 64], (0, 8));
+    }
+}
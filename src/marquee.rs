@@ -0,0 +1,235 @@
+use std::time::Duration;
+use crate::font::Font;
+use crate::img_buffer::{ImgBuffer, Rect, Rgb};
+
+/// How a marquee moves once its text has scrolled all the way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollMode {
+    /// Keep scrolling left, with the next copy of the text `gap` pixels
+    /// after the end of the last one.
+    Loop { gap: usize },
+    /// Scroll left until the end of the text shows, then back to the start.
+    PingPong,
+}
+
+/// Text that scrolls horizontally within a region when it is too wide to
+/// fit, and sits still at the left when it fits. Call `advance` once per
+/// rendered frame with the time since the last one.
+pub struct Marquee {
+    text: String,
+    text_width: usize,
+    region: Rect,
+    /// Pixels per second.
+    speed: f32,
+    mode: ScrollMode,
+    /// Time to hold still at the start and, in ping-pong mode, at the end.
+    pause: Duration,
+    paused: Duration,
+    /// Pixels scrolled from the start.
+    offset: f32,
+    backwards: bool,
+}
+
+impl Marquee {
+    pub fn new(text: &str, font: &Font, region: Rect, speed: f32) -> Self {
+        Self {
+            text: text.to_string(),
+            text_width: font.text_width(text),
+            region,
+            speed,
+            mode: ScrollMode::Loop { gap: 16 },
+            pause: Duration::ZERO,
+            paused: Duration::ZERO,
+            offset: 0.0,
+            backwards: false,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ScrollMode) {
+        self.mode = mode;
+        self.restart();
+    }
+
+    pub fn set_pause(&mut self, pause: Duration) {
+        self.pause = pause;
+        self.restart();
+    }
+
+    /// Change the text and scroll it from the start.
+    pub fn set_text(&mut self, text: &str, font: &Font) {
+        self.text = text.to_string();
+        self.text_width = font.text_width(text);
+        self.restart();
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn restart(&mut self) {
+        self.offset = 0.0;
+        self.paused = Duration::ZERO;
+        self.backwards = false;
+    }
+
+    fn scrolls(&self) -> bool {
+        self.text_width > self.region.width
+    }
+
+    /// Move the text on by `elapsed`.
+    pub fn advance(&mut self, elapsed: Duration) {
+        if !self.scrolls() {
+            return;
+        }
+
+        let hold = self.pause.saturating_sub(self.paused).min(elapsed);
+        self.paused += hold;
+        let step = self.speed * (elapsed - hold).as_secs_f32();
+
+        match self.mode {
+            ScrollMode::Loop { gap } => {
+                let period = (self.text_width + gap) as f32;
+                self.offset += step;
+
+                if self.offset >= period {
+                    self.offset = if self.pause.is_zero() { self.offset % period } else { 0.0 };
+                    self.paused = Duration::ZERO;
+                }
+            },
+            ScrollMode::PingPong => {
+                let end = (self.text_width - self.region.width) as f32;
+                self.offset += if self.backwards { -step } else { step };
+
+                // Only the end being scrolled towards turns the text round,
+                // so holding still at the other one doesn't restart the pause
+                let turn = if self.backwards { self.offset <= 0.0 } else { self.offset >= end };
+
+                if turn {
+                    self.offset = self.offset.clamp(0.0, end);
+                    self.backwards = !self.backwards;
+                    self.paused = Duration::ZERO;
+                }
+            },
+        }
+    }
+
+    pub fn draw<C: Into<Rgb>>(&self, image: &mut ImgBuffer, font: &Font, color: C) {
+        let color = color.into();
+        let x = self.region.x - self.offset.round() as isize;
+
+        image.push_clip(self.region);
+        image.draw_str(&self.text, font, x, self.region.y, color);
+
+        if let ScrollMode::Loop { gap } = self.mode {
+            if self.scrolls() {
+                image.draw_str(&self.text, font, x + (self.text_width + gap) as isize, self.region.y, color);
+            }
+        }
+
+        image.pop_clip();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::load("fonts/metronorth.toml").unwrap()
+    }
+
+    fn secs(s: f32) -> Duration {
+        Duration::from_secs_f32(s)
+    }
+
+    fn assert_offset(marquee: &Marquee, offset: f32) {
+        assert!((marquee.offset - offset).abs() < 0.01, "offset {} != {}", marquee.offset, offset);
+    }
+
+    /// "IIIIII" is 11 pixels wide in the Metro-North font.
+    fn marquee(font: &Font) -> Marquee {
+        Marquee::new("IIIIII", font, Rect::new(0, 0, 5, 7), 10.0)
+    }
+
+    #[test]
+    fn short_text_stays_put() {
+        let font = font();
+        let mut marquee = Marquee::new("II", &font, Rect::new(0, 0, 5, 7), 10.0);
+        marquee.advance(secs(1.0));
+        assert_offset(&marquee, 0.0);
+    }
+
+    #[test]
+    fn loop_starts_over_after_the_gap() {
+        let font = font();
+        let mut marquee = marquee(&font);
+        marquee.set_mode(ScrollMode::Loop { gap: 4 });
+
+        marquee.advance(secs(1.0));
+        assert_offset(&marquee, 10.0);
+        marquee.advance(secs(0.6));
+        assert_offset(&marquee, 1.0);
+    }
+
+    #[test]
+    fn pause_holds_at_the_start() {
+        let font = font();
+        let mut marquee = marquee(&font);
+        marquee.set_pause(secs(1.0));
+
+        marquee.advance(secs(0.5));
+        assert_offset(&marquee, 0.0);
+        marquee.advance(secs(0.7));
+        assert_offset(&marquee, 2.0);
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let font = font();
+        let mut marquee = marquee(&font);
+        marquee.set_mode(ScrollMode::PingPong);
+
+        marquee.advance(secs(1.0));
+        assert_offset(&marquee, 6.0);
+        marquee.advance(secs(0.2));
+        assert_offset(&marquee, 4.0);
+        marquee.advance(secs(1.0));
+        assert_offset(&marquee, 0.0);
+    }
+
+    #[test]
+    fn ping_pong_pauses_at_both_ends() {
+        let font = font();
+        let mut marquee = marquee(&font);
+        marquee.set_mode(ScrollMode::PingPong);
+        marquee.set_pause(secs(0.5));
+
+        let frames = |marquee: &mut Marquee, n| {
+            for _ in 0..n {
+                marquee.advance(secs(1.0 / 30.0));
+            }
+        };
+
+        frames(&mut marquee, 30);
+        assert_offset(&marquee, 5.0);
+        frames(&mut marquee, 15);
+        assert_offset(&marquee, 6.0);
+        frames(&mut marquee, 15);
+        assert_offset(&marquee, 2.0);
+    }
+
+    #[test]
+    fn text_is_clipped_to_the_region() {
+        let font = font();
+        let mut marquee = marquee(&font);
+        marquee.advance(secs(0.1));
+
+        let mut image = ImgBuffer::new(8, 7);
+        marquee.draw(&mut image, &font, Rgb::new(255, 255, 255));
+
+        // The first I is scrolled off, the next two show and the rest are
+        // past the region
+        let lit: Vec<bool> = (0..8).map(|x| image.pixel(x, 0) != Rgb::default()).collect();
+        assert_eq!(lit, vec![false, true, false, true, false, false, false, false]);
+    }
+}
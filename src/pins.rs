@@ -0,0 +1,324 @@
+use std::time::Duration;
+use rppal::gpio::{Gpio, OutputPin};
+use spin_sleep::sleep;
+
+/// The pin operations `Hub75Panel` needs to drive a panel. Implementations
+/// decide how the pins are actually toggled.
+pub trait Hub75Pins {
+    /// Put the upper (bits 0-2) and lower (bits 3-5) RGB values on the data
+    /// pins.
+    fn set_pins_for_byte(&mut self, byte: u8);
+    fn clock(&mut self);
+    fn latch(&mut self);
+    /// Hold LAT at a level across several clocks, as driver chips with
+    /// configuration registers expect.
+    fn set_latch(&mut self, high: bool);
+    /// Drive the address lines (A, B, C, ...) with the binary value of `row`.
+    fn select_row(&mut self, row: usize);
+    /// Number of address lines wired up, which limits the scan rate.
+    fn line_count(&self) -> usize;
+    /// Drive the active-low OE pin. `true` lights the latched row.
+    fn output_enable(&mut self, enabled: bool);
+    /// Turn the output off and clear the address lines.
+    fn blank(&mut self);
+
+    /// Put one column of data on the pins and clock it in. Backends that can
+    /// change several pins at once override this to save writes.
+    fn shift_byte(&mut self, byte: u8) {
+        self.set_pins_for_byte(byte);
+        self.clock();
+    }
+}
+
+/// Lets the backend be picked at runtime.
+impl<P: Hub75Pins + ?Sized> Hub75Pins for Box<P> {
+    fn set_pins_for_byte(&mut self, byte: u8) {
+        (**self).set_pins_for_byte(byte)
+    }
+
+    fn clock(&mut self) {
+        (**self).clock()
+    }
+
+    fn latch(&mut self) {
+        (**self).latch()
+    }
+
+    fn set_latch(&mut self, high: bool) {
+        (**self).set_latch(high)
+    }
+
+    fn select_row(&mut self, row: usize) {
+        (**self).select_row(row)
+    }
+
+    fn line_count(&self) -> usize {
+        (**self).line_count()
+    }
+
+    fn output_enable(&mut self, enabled: bool) {
+        (**self).output_enable(enabled)
+    }
+
+    fn blank(&mut self) {
+        (**self).blank()
+    }
+
+    fn shift_byte(&mut self, byte: u8) {
+        (**self).shift_byte(byte)
+    }
+}
+
+pub struct Hub75PinNums<const LC: usize> {
+    pub lines: [u8; LC],
+    pub r: [u8; 2],
+    pub g: [u8; 2],
+    pub b: [u8; 2],
+    pub clk: u8,
+    pub lat: u8,
+    pub oe: u8,
+}
+
+/// Drives the panel through the Raspberry Pi GPIO using rppal.
+pub struct RppalPins<const LC: usize> {
+    pub lines: [OutputPin; LC],
+    pub r: [OutputPin; 2],
+    pub g: [OutputPin; 2],
+    pub b: [OutputPin; 2],
+    pub clk: OutputPin,
+    pub lat: OutputPin,
+    pub oe: OutputPin,
+}
+
+impl<const LC: usize> RppalPins<LC> {
+    pub fn from_pin_nums(pin_nums: &Hub75PinNums<LC>) -> Result<RppalPins<LC>, rppal::gpio::Error> {
+        let gpio = Gpio::new()?;
+
+        let outputs = |nums: &[u8]| -> Result<Vec<OutputPin>, rppal::gpio::Error> {
+            nums.iter().map(|x| Ok(gpio.get(*x)?.into_output_low())).collect()
+        };
+
+        Ok(RppalPins {
+            lines: outputs(&pin_nums.lines)?.try_into().unwrap(),
+            r: outputs(&pin_nums.r)?.try_into().unwrap(),
+            g: outputs(&pin_nums.g)?.try_into().unwrap(),
+            b: outputs(&pin_nums.b)?.try_into().unwrap(),
+            clk: gpio.get(pin_nums.clk)?.into_output_low(),
+            lat: gpio.get(pin_nums.lat)?.into_output_low(),
+            oe: gpio.get(pin_nums.oe)?.into_output_low(),
+        })
+    }
+}
+
+impl<const LC: usize> Hub75Pins for RppalPins<LC> {
+    fn set_pins_for_byte(&mut self, byte: u8) {
+        self.r[0].write((byte & 1 != 0).into());
+        self.g[0].write((byte & 2 != 0).into());
+        self.b[0].write((byte & 4 != 0).into());
+        self.r[1].write((byte & 8 != 0).into());
+        self.g[1].write((byte & 16 != 0).into());
+        self.b[1].write((byte & 32 != 0).into());
+    }
+
+    fn clock(&mut self) {
+        self.clk.set_high();
+        sleep(Duration::new(0, 1));
+        self.clk.set_low();
+    }
+
+    fn latch(&mut self) {
+        self.lat.set_high();
+        sleep(Duration::new(0, 1));
+        self.lat.set_low();
+    }
+
+    fn set_latch(&mut self, high: bool) {
+        self.lat.write(high.into());
+    }
+
+    fn select_row(&mut self, row: usize) {
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.write((row & (1 << i) != 0).into());
+        }
+    }
+
+    fn line_count(&self) -> usize {
+        LC
+    }
+
+    fn output_enable(&mut self, enabled: bool) {
+        self.oe.write((!enabled).into());
+    }
+
+    fn blank(&mut self) {
+        self.output_enable(false);
+
+        for line in self.lines.iter_mut() {
+            line.set_low();
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pin {
+    Line(usize),
+    R(usize),
+    G(usize),
+    B(usize),
+    Clk,
+    Lat,
+    Oe,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PinEvent {
+    pub pin: Pin,
+    pub high: bool,
+}
+
+/// Keeps track of every pin level and logs each transition instead of
+/// touching hardware, so the signals `Hub75Panel` produces can be checked
+/// off-device.
+pub struct RecordingPins {
+    lines: Vec<bool>,
+    rgb: u8,
+    clk: bool,
+    lat: bool,
+    oe: bool,
+    events: Vec<PinEvent>,
+}
+
+impl RecordingPins {
+    pub fn new(line_count: usize) -> Self {
+        Self {
+            lines: vec![false; line_count],
+            rgb: 0,
+            clk: false,
+            lat: false,
+            oe: false,
+            events: vec![],
+        }
+    }
+
+    pub fn events(&self) -> &[PinEvent] {
+        &self.events
+    }
+
+    pub fn take_events(&mut self) -> Vec<PinEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn write(&mut self, pin: Pin, high: bool) {
+        let level = match pin {
+            Pin::Line(i) => &mut self.lines[i],
+            Pin::Clk => &mut self.clk,
+            Pin::Lat => &mut self.lat,
+            Pin::Oe => &mut self.oe,
+            Pin::R(_) | Pin::G(_) | Pin::B(_) => unreachable!("RGB pins are tracked as a byte"),
+        };
+
+        if *level != high {
+            *level = high;
+            self.events.push(PinEvent { pin, high });
+        }
+    }
+}
+
+impl Hub75Pins for RecordingPins {
+    fn set_pins_for_byte(&mut self, byte: u8) {
+        let pins = [Pin::R(0), Pin::G(0), Pin::B(0), Pin::R(1), Pin::G(1), Pin::B(1)];
+
+        for (bit, pin) in pins.into_iter().enumerate() {
+            let high = byte & (1 << bit) != 0;
+
+            if (self.rgb & (1 << bit) != 0) != high {
+                self.rgb ^= 1 << bit;
+                self.events.push(PinEvent { pin, high });
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        self.write(Pin::Clk, true);
+        self.write(Pin::Clk, false);
+    }
+
+    fn latch(&mut self) {
+        self.write(Pin::Lat, true);
+        self.write(Pin::Lat, false);
+    }
+
+    fn set_latch(&mut self, high: bool) {
+        self.write(Pin::Lat, high);
+    }
+
+    fn select_row(&mut self, row: usize) {
+        for i in 0..self.lines.len() {
+            self.write(Pin::Line(i), row & (1 << i) != 0);
+        }
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn output_enable(&mut self, enabled: bool) {
+        self.write(Pin::Oe, !enabled);
+    }
+
+    fn blank(&mut self) {
+        self.output_enable(false);
+
+        for i in 0..self.lines.len() {
+            self.write(Pin::Line(i), false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transitions_are_recorded() {
+        let mut pins = RecordingPins::new(4);
+        pins.set_pins_for_byte(0b000101);
+        pins.set_pins_for_byte(0b000100);
+        pins.clock();
+
+        assert_eq!(pins.events(), &[
+            PinEvent { pin: Pin::R(0), high: true },
+            PinEvent { pin: Pin::B(0), high: true },
+            PinEvent { pin: Pin::R(0), high: false },
+            PinEvent { pin: Pin::Clk, high: true },
+            PinEvent { pin: Pin::Clk, high: false },
+        ]);
+    }
+
+    #[test]
+    fn blank_disables_output() {
+        let mut pins = RecordingPins::new(2);
+        pins.select_row(3);
+        pins.output_enable(true);
+        pins.blank();
+
+        assert!(pins.oe);
+        assert_eq!(pins.lines, vec![false, false]);
+        assert_eq!(pins.events().last(), Some(&PinEvent { pin: Pin::Line(1), high: false }));
+    }
+
+    #[test]
+    fn select_row_sets_address_lines() {
+        let mut pins = RecordingPins::new(4);
+        pins.select_row(5);
+        pins.select_row(6);
+
+        assert_eq!(pins.take_events(), vec![
+            PinEvent { pin: Pin::Line(0), high: true },
+            PinEvent { pin: Pin::Line(2), high: true },
+            PinEvent { pin: Pin::Line(0), high: false },
+            PinEvent { pin: Pin::Line(1), high: true },
+        ]);
+        assert!(pins.events().is_empty());
+    }
+}
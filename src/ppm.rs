@@ -1,5 +1,6 @@
 use std::fs::read;
 use std::error::Error;
+use crate::img_buffer::Rgb;
 
 pub struct PPM {
     format: String,
@@ -38,6 +39,12 @@ impl Pixel {
     }
 }
 
+impl From<&Pixel> for Rgb {
+    fn from(pixel: &Pixel) -> Self {
+        Rgb::new(pixel.r, pixel.g, pixel.b)
+    }
+}
+
 impl PPM {
     pub fn from_vec(v: &Vec<u8>) -> Self {
         let mut state = ParseState::Start;
@@ -126,6 +133,14 @@ impl PPM {
         &self.format
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     pub fn pixel(&self, x: usize, y: usize) -> Option<&Pixel> {
         let index = y * self.width + x;
         self.pixels.get(index)
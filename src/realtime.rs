@@ -0,0 +1,117 @@
+use std::io;
+use std::mem;
+use serde::Deserialize;
+
+/// Scheduling for the strobe thread. Other threads waking up in the middle of
+/// a row show up as flicker, so the strobe thread can be given a realtime
+/// priority and a core of its own.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct RealtimeConfig {
+    /// SCHED_FIFO priority from 1 to 99, or normal scheduling when unset.
+    pub priority: Option<i32>,
+    /// CPU to pin the strobe thread to, ideally one left out of the kernel's
+    /// scheduling with `isolcpus`.
+    pub cpu: Option<usize>,
+    /// Lock all memory so the strobe thread never waits on a page fault.
+    pub lock_memory: bool,
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Run the calling thread under SCHED_FIFO at `priority`.
+pub fn set_fifo_priority(priority: i32) -> io::Result<()> {
+    let (min, max) = unsafe {
+        (libc::sched_get_priority_min(libc::SCHED_FIFO), libc::sched_get_priority_max(libc::SCHED_FIFO))
+    };
+
+    if !(min..=max).contains(&priority) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Priority {} is outside {}-{}", priority, min, max),
+        ));
+    }
+
+    let param = libc::sched_param { sched_priority: priority };
+    let result = unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+
+    // pthread functions return the error instead of setting errno
+    match result {
+        0 => Ok(()),
+        e => Err(io::Error::from_raw_os_error(e)),
+    }
+}
+
+/// Keep the calling thread on one CPU.
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("CPU {} is beyond the last possible CPU {}", cpu, libc::CPU_SETSIZE - 1),
+        ));
+    }
+
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        check(libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set))
+    }
+}
+
+/// Lock the process' current and future memory into RAM.
+pub fn lock_memory() -> io::Result<()> {
+    check(unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) })
+}
+
+impl RealtimeConfig {
+    /// Apply the settings to the calling thread. Anything that fails, usually
+    /// for lack of root or CAP_SYS_NICE, is reported and the thread carries on
+    /// with normal scheduling.
+    pub fn apply(&self) {
+        if self.lock_memory {
+            if let Err(e) = lock_memory() {
+                println!("Warning: couldn't lock memory, page faults may cause flicker: {}", e);
+            }
+        }
+
+        if let Some(cpu) = self.cpu {
+            if let Err(e) = pin_to_cpu(cpu) {
+                println!("Warning: couldn't pin the strobe thread to CPU {}: {}", cpu, e);
+            }
+        }
+
+        if let Some(priority) = self.priority {
+            if let Err(e) = set_fifo_priority(priority) {
+                println!("Warning: couldn't use realtime priority {}, expect more flicker: {}", priority, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_priority_is_rejected() {
+        assert_eq!(set_fifo_priority(0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(set_fifo_priority(100).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn out_of_range_cpu_is_rejected() {
+        assert_eq!(pin_to_cpu(libc::CPU_SETSIZE as usize).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn it_can_be_configured() {
+        let config: RealtimeConfig = toml::from_str("priority = 90\ncpu = 3").unwrap();
+        assert_eq!(config, RealtimeConfig { priority: Some(90), cpu: Some(3), lock_memory: false });
+    }
+}
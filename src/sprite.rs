@@ -0,0 +1,163 @@
+use std::error::Error;
+use crate::color_correction::{ColorCorrection, ColorTables};
+use crate::img_buffer::{ImgBuffer, Rect, Rgb};
+use crate::ppm::PPM;
+
+/// A small image such as a logo or route bullet, drawn onto an `ImgBuffer`
+/// with `blit`. Transparent pixels are None.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sprite {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<Rgb>>,
+}
+
+impl Sprite {
+    pub fn from_ppm(ppm: &PPM) -> Self {
+        let (width, height) = (ppm.width(), ppm.height());
+        let pixels = (0..width * height)
+            .map(|i| Some(ppm.pixel(i % width, i / width).map_or(Rgb::default(), Rgb::from)))
+            .collect();
+
+        Self { width, height, pixels }
+    }
+
+    pub fn load(filename: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::from_ppm(&PPM::load(filename)?))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Make every pixel of color `key` transparent.
+    pub fn with_key<C: Into<Rgb>>(mut self, key: C) -> Self {
+        let key = Some(key.into());
+        for pixel in self.pixels.iter_mut().filter(|p| **p == key) {
+            *pixel = None;
+        }
+        self
+    }
+
+    /// The part of the sprite inside `rect`, for picking one image out of a
+    /// sprite sheet. Parts of `rect` outside the sprite are left out.
+    pub fn crop(&self, rect: Rect) -> Sprite {
+        let area = rect.intersect(&Rect::new(0, 0, self.width, self.height));
+        let pixels = (area.y..area.bottom())
+            .flat_map(|y| (area.x..area.right()).map(move |x| (x as usize, y as usize)))
+            .map(|(x, y)| self.pixel(x, y))
+            .collect();
+
+        Sprite { width: area.width, height: area.height, pixels }
+    }
+
+    /// Snap every color to the levels a panel with `correction` and `depth`
+    /// bits per channel shows, so a preview only has the shades the panel can
+    /// tell apart. Colors the gamma curve turns off become black.
+    pub fn quantize(&mut self, correction: &ColorCorrection, depth: usize) {
+        assert!((1..=8).contains(&depth), "Color depth must be 1 to 8 bits");
+        let tables = ColorTables::new(correction, depth);
+
+        for color in self.pixels.iter_mut().flatten() {
+            *color = tables.quantize(*color);
+        }
+    }
+}
+
+impl ImgBuffer {
+    /// Draw `sprite` with its top left corner at (x, y), skipping transparent
+    /// pixels.
+    pub fn blit(&mut self, sprite: &Sprite, x: isize, y: isize) {
+        for sy in 0..sprite.height {
+            for sx in 0..sprite.width {
+                if let Some(color) = sprite.pixel(sx, sy) {
+                    self.plot(x + sx as isize, y + sy as isize, color);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+
+    /// A 3x2 sheet: red, magenta, blue on top, then green, magenta, white.
+    fn sheet() -> Sprite {
+        let ppm = PPM::from_vec(&b"P6 3 2 255\n\
+            \xff\x00\x00\xff\x00\xff\x00\x00\xff\
+            \x00\xff\x00\xff\x00\xff\xff\xff\xff".to_vec());
+        Sprite::from_ppm(&ppm)
+    }
+
+    #[test]
+    fn key_color_is_transparent() {
+        let sprite = sheet().with_key(Rgb::new(255, 0, 255));
+        let mut image = ImgBuffer::new(3, 2);
+        image.set_pixel(1, 0, Color::Teal);
+        image.blit(&sprite, 0, 0);
+
+        assert_eq!(image.pixel(0, 0), Color::Red.into());
+        assert_eq!(image.pixel(1, 0), Color::Teal.into());
+        assert_eq!(image.pixel(1, 1), Rgb::default());
+        assert_eq!(image.pixel(2, 1), Color::White.into());
+    }
+
+    #[test]
+    fn blit_is_clipped() {
+        let mut image = ImgBuffer::new(2, 2);
+        image.blit(&sheet(), -1, 1);
+
+        assert_eq!(image.pixel(0, 1), Rgb::new(255, 0, 255));
+        assert_eq!(image.pixel(1, 1), Color::Blue.into());
+        assert_eq!(image.pixel(0, 0), Rgb::default());
+    }
+
+    #[test]
+    fn sprites_can_be_cropped_from_a_sheet() {
+        let sprite = sheet().crop(Rect::new(2, 0, 5, 5));
+        assert_eq!((sprite.width(), sprite.height()), (1, 2));
+        assert_eq!(sprite.pixel(0, 0), Some(Color::Blue.into()));
+        assert_eq!(sprite.pixel(0, 1), Some(Color::White.into()));
+    }
+
+    #[test]
+    fn colors_snap_to_panel_levels() {
+        let ppm = PPM::from_vec(&b"P6 1 1 255\n\x40\x80\xc0".to_vec());
+        let mut sprite = Sprite::from_ppm(&ppm);
+
+        sprite.quantize(&ColorCorrection::LINEAR, 1);
+        assert_eq!(sprite.pixel(0, 0), Some(Rgb::new(0, 128, 128)));
+
+        let mut sprite = Sprite::from_ppm(&ppm);
+        sprite.quantize(&ColorCorrection::LINEAR, 2);
+        assert_eq!(sprite.pixel(0, 0), Some(Rgb::new(64, 128, 192)));
+    }
+
+    #[test]
+    fn quantize_follows_gamma() {
+        let ppm = PPM::from_vec(&b"P6 4 1 255\n\
+            \x11\x11\x11\x22\x22\x22\x33\x33\x33\x44\x44\x44".to_vec());
+        let mut sprite = Sprite::from_ppm(&ppm);
+        sprite.quantize(&ColorCorrection::default(), 4);
+
+        // A 4 bit panel with gamma 2.2 shows all of these as off
+        for x in 0..4 {
+            assert_eq!(sprite.pixel(x, 0), Some(Rgb::default()));
+        }
+
+        let tables = ColorTables::new(&ColorCorrection::default(), 4);
+        let white = tables.quantize(Rgb::new(255, 255, 255));
+        assert_eq!(white, tables.quantize(white));
+        assert_eq!(tables.apply(white), Rgb::new(15, 15, 15));
+    }
+}
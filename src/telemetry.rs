@@ -0,0 +1,180 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the jitter histogram buckets in microseconds. Anything
+/// above the last bound goes into one extra bucket.
+pub const JITTER_BUCKETS_US: [u64; 8] = [1, 2, 5, 10, 20, 50, 100, 500];
+
+/// Refresh timing measured by the strobe thread over one report interval.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimingStats {
+    pub rows_per_second: f64,
+    pub frames_per_second: f64,
+    /// Longest time spent strobing a single row.
+    pub worst_row_time: Duration,
+    /// Rows that took longer than the row period, so the next one started late.
+    pub missed_deadlines: u64,
+    /// Row counts by how far the time between row starts was from the row
+    /// period, bucketed by `JITTER_BUCKETS_US`.
+    pub jitter: [u64; JITTER_BUCKETS_US.len() + 1],
+}
+
+impl fmt::Display for TimingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0} rows/s, {:.1} frames/s, worst row {:?}, {} missed, jitter",
+               self.rows_per_second, self.frames_per_second, self.worst_row_time, self.missed_deadlines)?;
+
+        for (bound, count) in JITTER_BUCKETS_US.iter().zip(self.jitter) {
+            write!(f, " <={}us:{}", bound, count)?;
+        }
+
+        write!(f, " >{}us:{}", JITTER_BUCKETS_US[JITTER_BUCKETS_US.len() - 1], self.jitter[JITTER_BUCKETS_US.len()])
+    }
+}
+
+/// Read side of the telemetry. Clones share the same stats, so any thread can
+/// ask for the latest numbers.
+#[derive(Clone, Default)]
+pub struct Telemetry(Arc<Mutex<Option<TimingStats>>>);
+
+impl Telemetry {
+    /// Stats for the most recently completed interval, or None before the
+    /// first one is done.
+    pub fn latest(&self) -> Option<TimingStats> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Write side of the telemetry, owned by the strobe thread. Counts are kept
+/// locally and only published once per interval, so recording a row never
+/// waits on a lock.
+pub struct TimingRecorder {
+    telemetry: Telemetry,
+    row_period: Duration,
+    interval: Duration,
+    interval_start: Option<Instant>,
+    last_row_start: Option<Instant>,
+    rows: u64,
+    frames: u64,
+    stats: TimingStats,
+}
+
+impl TimingRecorder {
+    /// Record rows meant to be strobed every `row_period`, publishing stats
+    /// to `telemetry` every `interval`.
+    pub fn new(telemetry: Telemetry, row_period: Duration, interval: Duration) -> Self {
+        Self {
+            telemetry,
+            row_period,
+            interval,
+            interval_start: None,
+            last_row_start: None,
+            rows: 0,
+            frames: 0,
+            stats: TimingStats::default(),
+        }
+    }
+
+    /// Record one row strobed from `start` to `end`. `frame_start` is set for
+    /// the first row of each frame.
+    pub fn record_row(&mut self, start: Instant, end: Instant, frame_start: bool) {
+        let interval_start = *self.interval_start.get_or_insert(start);
+
+        let row_time = end - start;
+        self.stats.worst_row_time = self.stats.worst_row_time.max(row_time);
+        if row_time > self.row_period {
+            self.stats.missed_deadlines += 1;
+        }
+
+        if let Some(last) = self.last_row_start {
+            let period = start - last;
+            let jitter = period.max(self.row_period) - period.min(self.row_period);
+            let us = jitter.as_micros() as u64;
+            let bucket = JITTER_BUCKETS_US.iter().position(|&b| us <= b).unwrap_or(JITTER_BUCKETS_US.len());
+            self.stats.jitter[bucket] += 1;
+        }
+        self.last_row_start = Some(start);
+
+        self.rows += 1;
+        if frame_start {
+            self.frames += 1;
+        }
+
+        let elapsed = end - interval_start;
+        if elapsed >= self.interval {
+            self.publish(elapsed);
+            self.interval_start = Some(end);
+        }
+    }
+
+    fn publish(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut stats = std::mem::take(&mut self.stats);
+        stats.rows_per_second = self.rows as f64 / seconds;
+        stats.frames_per_second = self.frames as f64 / seconds;
+        self.rows = 0;
+        self.frames = 0;
+
+        // Skip this interval rather than wait on a reader
+        if let Ok(mut latest) = self.telemetry.0.try_lock() {
+            *latest = Some(stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn nothing_before_the_first_interval() {
+        let telemetry = Telemetry::default();
+        let mut recorder = TimingRecorder::new(telemetry.clone(), ms(1), ms(1000));
+        let start = Instant::now();
+
+        recorder.record_row(start, start + ms(1), true);
+        assert_eq!(telemetry.latest(), None);
+    }
+
+    #[test]
+    fn rates_and_deadlines_are_published() {
+        let telemetry = Telemetry::default();
+        let mut recorder = TimingRecorder::new(telemetry.clone(), ms(10), ms(100));
+        let start = Instant::now();
+
+        // Rows 10 ms apart in frames of five rows, the fourth one slow
+        for row in 0..11 {
+            let row_start = start + ms(10 * row);
+            let row_time = if row == 3 { ms(12) } else { ms(4) };
+            recorder.record_row(row_start, row_start + row_time, row % 5 == 0);
+        }
+
+        let stats = telemetry.latest().unwrap();
+        assert_eq!(stats.rows_per_second.round(), 106.0);
+        assert_eq!(stats.frames_per_second.round(), 29.0);
+        assert_eq!(stats.worst_row_time, ms(12));
+        assert_eq!(stats.missed_deadlines, 1);
+    }
+
+    #[test]
+    fn jitter_is_bucketed() {
+        let telemetry = Telemetry::default();
+        let mut recorder = TimingRecorder::new(telemetry.clone(), ms(1), ms(10));
+        let start = Instant::now();
+        let us = Duration::from_micros;
+
+        // Row periods of 1000, 1003, 1600 and 8000 us
+        let starts = [0, 1000, 2003, 3603, 11603];
+        for s in starts {
+            recorder.record_row(start + us(s), start + us(s + 100), false);
+        }
+
+        let jitter = telemetry.latest().unwrap().jitter;
+        assert_eq!(jitter, [1, 0, 1, 0, 0, 0, 0, 0, 2]);
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+use crate::brightness::Brightness;
+use crate::img_buffer::{ImgBuffer, Rgb};
+
+/// Shows images in a terminal instead of on a panel, for working on the
+/// board without a Pi. Every character cell is an upper half block with the
+/// top pixel as foreground and the bottom pixel as background, so the
+/// terminal needs 24 bit color. Each image is drawn over the previous one,
+/// found from the cursor position saved just below it, so messages printed
+/// in between end up below the image. Messages that scroll the terminal
+/// still move the image and leave a copy of it behind.
+pub struct TerminalDisplay<W: Write> {
+    out: W,
+    /// Character rows of the last image drawn, which the next one replaces.
+    drawn_rows: usize,
+    brightness: Brightness,
+}
+
+impl<W: Write> TerminalDisplay<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            drawn_rows: 0,
+            brightness: Brightness::new(100),
+        }
+    }
+
+    /// Handle to the simulated brightness, shared like
+    /// `Hub75Panel::brightness`.
+    pub fn brightness(&self) -> Brightness {
+        self.brightness.clone()
+    }
+
+    pub fn show(&mut self, image: &ImgBuffer) -> io::Result<()> {
+        let level = self.brightness.get() as u32;
+        let dim = |c: Rgb| {
+            let scale = |v: u8| (v as u32 * level / 100) as u8;
+            (scale(c.r), scale(c.g), scale(c.b))
+        };
+
+        let mut text = String::new();
+
+        // Back up over the last image rather than clearing, so messages
+        // printed above it stay put. Going back to the saved position first
+        // skips any lines printed after it.
+        if self.drawn_rows > 0 {
+            text.push_str(&format!("\x1b8\x1b[{}A\r", self.drawn_rows));
+        }
+
+        for y in (0..image.height()).step_by(2) {
+            for x in 0..image.width() {
+                let (tr, tg, tb) = dim(image.pixel(x, y));
+                let (br, bg, bb) = if y + 1 < image.height() { dim(image.pixel(x, y + 1)) } else { (0, 0, 0) };
+                text.push_str(&format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", tr, tg, tb, br, bg, bb));
+            }
+            text.push_str("\x1b[0m\n");
+        }
+
+        // Saved after drawing rather than before, so it's still right if
+        // the image scrolled the terminal
+        text.push_str("\x1b7");
+
+        self.drawn_rows = image.height().div_ceil(2);
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+
+    #[test]
+    fn pixel_pairs_become_half_blocks() {
+        let mut image = ImgBuffer::new(1, 3);
+        image.set_pixel(0, 0, Color::Red);
+        image.set_pixel(0, 1, Color::Blue);
+        image.set_pixel(0, 2, Color::Green);
+
+        let mut display = TerminalDisplay::new(vec![]);
+        display.show(&image).unwrap();
+
+        assert_eq!(String::from_utf8(display.out).unwrap(),
+                   "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}\x1b[0m\n\
+                    \x1b[38;2;0;255;0m\x1b[48;2;0;0;0m\u{2580}\x1b[0m\n\x1b7");
+    }
+
+    #[test]
+    fn later_images_are_drawn_in_place() {
+        let mut display = TerminalDisplay::new(vec![]);
+        display.show(&ImgBuffer::new(1, 4)).unwrap();
+        display.out.clear();
+
+        display.brightness().set(50);
+        let mut image = ImgBuffer::new(1, 4);
+        image.set_pixel(0, 0, Color::White);
+        display.show(&image).unwrap();
+
+        let text = String::from_utf8(display.out).unwrap();
+        assert!(text.starts_with("\x1b8\x1b[2A\r\x1b[38;2;127;127;127m"));
+    }
+}
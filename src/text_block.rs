@@ -0,0 +1,168 @@
+use std::mem;
+use crate::font::Font;
+use crate::img_buffer::{Align, ImgBuffer, Rect, Rgb};
+
+const ELLIPSIS: &str = "...";
+
+/// How a block of text is laid out in its rectangle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextBlockStyle {
+    /// Blank rows between lines.
+    pub line_spacing: usize,
+    pub align: Align,
+    /// End the last line with "..." when the text doesn't all fit.
+    pub ellipsis: bool,
+}
+
+impl Default for TextBlockStyle {
+    fn default() -> Self {
+        Self {
+            line_spacing: 1,
+            align: Align::Left,
+            ellipsis: true,
+        }
+    }
+}
+
+/// Split a word into pieces no wider than `width`, each at least one
+/// character long.
+fn break_word(word: &str, font: &Font, width: usize) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+
+    for c in word.chars() {
+        piece.push(c);
+        if font.text_width(&piece) > width && piece.chars().count() > 1 {
+            piece.pop();
+            pieces.push(mem::replace(&mut piece, c.to_string()));
+        }
+    }
+
+    pieces.push(piece);
+    pieces
+}
+
+/// Break `text` into lines no wider than `width`, between words where
+/// possible. Words too long for a line are broken wherever they have to be,
+/// and newlines in the text always start a new line.
+pub fn wrap(text: &str, font: &Font, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+
+        for piece in paragraph.split_whitespace().flat_map(|word| break_word(word, font, width)) {
+            if line.is_empty() {
+                line = piece;
+                continue;
+            }
+
+            let joined = format!("{} {}", line, piece);
+            if font.text_width(&joined) <= width {
+                line = joined;
+            } else {
+                lines.push(mem::replace(&mut line, piece));
+            }
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// `line` shortened until it fits in `width` with an ellipsis on the end.
+fn with_ellipsis(line: &str, font: &Font, width: usize) -> String {
+    let mut line = line.to_string();
+
+    while !line.is_empty() && font.text_width(&format!("{}{}", line, ELLIPSIS)) > width {
+        line.pop();
+    }
+
+    format!("{}{}", line.trim_end(), ELLIPSIS)
+}
+
+impl ImgBuffer {
+    /// Draw `text` word wrapped inside `rect`. Returns false when there were
+    /// more lines than fit, in which case the rest are left out.
+    pub fn draw_text_block<C: Into<Rgb>>(&mut self, text: &str, font: &Font, rect: Rect, style: &TextBlockStyle, color: C) -> bool {
+        let color = color.into();
+        let pitch = font.line_height() + style.line_spacing;
+        let max_lines = (rect.height + style.line_spacing) / pitch.max(1);
+
+        let mut lines = wrap(text, font, rect.width);
+        let fits = lines.len() <= max_lines;
+
+        if !fits {
+            lines.truncate(max_lines);
+            if style.ellipsis {
+                if let Some(last) = lines.last_mut() {
+                    *last = with_ellipsis(last, font, rect.width);
+                }
+            }
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_rect = Rect::new(rect.x, rect.y + (i * pitch) as isize, rect.width, font.line_height());
+            self.draw_str_aligned(line, font, line_rect, style.align, color);
+        }
+
+        fits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> Font {
+        Font::load("fonts/metronorth.toml").unwrap()
+    }
+
+    #[test]
+    fn words_wrap_at_spaces() {
+        // "II II" is 9 pixels wide
+        let lines = wrap("II II II", &font(), 9);
+        assert_eq!(lines, vec!["II II", "II"]);
+    }
+
+    #[test]
+    fn long_words_are_broken() {
+        let lines = wrap("IIIIIII I", &font(), 5);
+        assert_eq!(lines, vec!["III", "III", "I I"]);
+    }
+
+    #[test]
+    fn newlines_start_new_lines() {
+        let lines = wrap("I\n\nI I", &font(), 20);
+        assert_eq!(lines, vec!["I", "", "I I"]);
+    }
+
+    #[test]
+    fn overflow_ends_in_ellipsis() {
+        let font = font();
+        let mut image = ImgBuffer::new(20, 16);
+        let style = TextBlockStyle::default();
+
+        // Two 7 pixel lines with one blank row fit in 16 rows
+        assert!(image.draw_text_block("II II", &font, Rect::new(0, 0, 20, 16), &style, Rgb::new(255, 0, 0)));
+        assert!(!image.draw_text_block("IIII IIII IIII", &font, Rect::new(0, 0, 14, 16), &style, Rgb::new(255, 0, 0)));
+
+        let lines = wrap("IIII IIII IIII", &font, 14);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(with_ellipsis(&lines[1], &font, 14), "I...");
+    }
+
+    #[test]
+    fn lines_are_spaced() {
+        let font = font();
+        let mut image = ImgBuffer::new(4, 20);
+        let style = TextBlockStyle { line_spacing: 3, ..TextBlockStyle::default() };
+        image.draw_text_block("I I", &font, Rect::new(0, 0, 1, 20), &style, Rgb::new(255, 0, 0));
+
+        let lit: Vec<bool> = (0..20).map(|y| image.pixel(0, y) != Rgb::default()).collect();
+        assert!(lit[0..7].iter().all(|&l| l));
+        assert!(lit[7..10].iter().all(|&l| !l));
+        assert!(lit[10..17].iter().all(|&l| l));
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+use crate::img_buffer::{ImgBuffer, Rgb};
+use crate::layout::Rotation;
+
+/// How the logical image is placed on the physical display, for signs
+/// mounted upside down or seen in a mirror. The image is flipped first, then
+/// rotated clockwise, then moved by the offset. Pixels moved off the display
+/// are dropped and the uncovered ones stay dark.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DisplayTransform {
+    pub rotation: Rotation,
+    /// Mirror left to right.
+    pub flip_x: bool,
+    /// Mirror top to bottom.
+    pub flip_y: bool,
+    pub offset_x: isize,
+    pub offset_y: isize,
+}
+
+impl DisplayTransform {
+    /// Size of the logical image for a `width` x `height` display.
+    pub fn logical_size(self, width: usize, height: usize) -> (usize, usize) {
+        self.rotation.inverse().size(width, height)
+    }
+
+    /// The logical pixel shown at (x, y) of a `width` x `height` display, if
+    /// there is one.
+    pub fn to_logical(self, x: usize, y: usize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let x = usize::try_from(x as isize - self.offset_x).ok().filter(|&x| x < width)?;
+        let y = usize::try_from(y as isize - self.offset_y).ok().filter(|&y| y < height)?;
+
+        let (lx, ly) = self.rotation.inverse().apply(x, y, width, height);
+        let (logical_width, logical_height) = self.logical_size(width, height);
+
+        Some((
+            if self.flip_x { logical_width - 1 - lx } else { lx },
+            if self.flip_y { logical_height - 1 - ly } else { ly },
+        ))
+    }
+}
+
+impl ImgBuffer {
+    /// The image as it appears on the physical display.
+    pub fn transformed(&self, transform: &DisplayTransform) -> ImgBuffer {
+        let (width, height) = transform.rotation.size(self.width(), self.height());
+        let mut output = ImgBuffer::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = transform.to_logical(x, y, width, height)
+                    .map_or(Rgb::default(), |(lx, ly)| self.pixel(lx, ly));
+                output.set_pixel(x, y, color);
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_buffer::Color;
+
+    fn marked() -> ImgBuffer {
+        let mut image = ImgBuffer::new(3, 2);
+        image.set_pixel(0, 0, Color::Red);
+        image.set_pixel(2, 1, Color::Blue);
+        image
+    }
+
+    #[test]
+    fn identity_changes_nothing() {
+        let output = marked().transformed(&DisplayTransform::default());
+        assert_eq!(output.pixel(0, 0), Color::Red.into());
+        assert_eq!(output.pixel(2, 1), Color::Blue.into());
+    }
+
+    #[test]
+    fn quarter_turn_swaps_the_size() {
+        let transform = DisplayTransform { rotation: Rotation::Cw90, ..Default::default() };
+        assert_eq!(transform.logical_size(2, 3), (3, 2));
+
+        let output = marked().transformed(&transform);
+        assert_eq!((output.width(), output.height()), (2, 3));
+        assert_eq!(output.pixel(1, 0), Color::Red.into());
+        assert_eq!(output.pixel(0, 2), Color::Blue.into());
+    }
+
+    #[test]
+    fn upside_down_and_mirrored() {
+        let transform = DisplayTransform { rotation: Rotation::Cw180, ..Default::default() };
+        let output = marked().transformed(&transform);
+        assert_eq!(output.pixel(2, 1), Color::Red.into());
+        assert_eq!(output.pixel(0, 0), Color::Blue.into());
+
+        let transform = DisplayTransform { flip_x: true, ..Default::default() };
+        let output = marked().transformed(&transform);
+        assert_eq!(output.pixel(2, 0), Color::Red.into());
+        assert_eq!(output.pixel(0, 1), Color::Blue.into());
+    }
+
+    #[test]
+    fn offset_drops_pixels_off_the_edge() {
+        let transform = DisplayTransform { offset_x: 1, offset_y: -1, ..Default::default() };
+        assert_eq!(transform.to_logical(1, 0, 3, 2), Some((0, 1)));
+        assert_eq!(transform.to_logical(0, 0, 3, 2), None);
+        assert_eq!(transform.to_logical(1, 1, 3, 2), None);
+    }
+
+    #[test]
+    fn it_can_be_configured() {
+        let transform: DisplayTransform = toml::from_str("rotation = 180\nflip_y = true").unwrap();
+        assert_eq!(transform.rotation, Rotation::Cw180);
+        assert!(transform.flip_y);
+        assert!(toml::from_str::<DisplayTransform>("rotation = 45").is_err());
+    }
+}